@@ -0,0 +1,405 @@
+//! Derive macros for `endpoint-libs`.
+//!
+//! The macros are re-exported from `endpoint_libs::model` and expand to paths rooted at
+//! `::endpoint_libs`, so the generated code only compiles in crates that depend on it.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Expr, ExprLit, Fields, Ident, Lit, LitInt, LitStr, Path};
+use syn::{Meta, Token, Variant};
+
+/// Derives `WsRequest` for a request struct, `WsResponse` for its response struct and a typed
+/// `EndpointSchema` built from the fields of both.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize, Clone, Endpoint)]
+/// #[endpoint(code = 10020, name = "UserListSymbols")]
+/// pub struct UserListSymbolsRequest {
+///     pub symbol: Option<String>,
+/// }
+///
+/// #[derive(Serialize, Deserialize, Clone, EndpointStruct)]
+/// pub struct UserListSymbolsResponse {
+///     pub data: Vec<String>,
+/// }
+/// ```
+///
+/// Struct level options of `#[endpoint(...)]`:
+/// - `code`: the method code (required)
+/// - `name`: the endpoint name (required), the struct must be named `{name}Request`
+/// - `response`: the response type (defaults to `{name}Response`)
+/// - `stream`: the stream response type, also derives `WsStreamRequest`
+/// - `description`: the endpoint description (defaults to the doc comment of the struct)
+///
+/// Fields accept `#[endpoint(ty = <expr>)]` to override the mapped `Type`, e.g. `Type::TimeStampMs`.
+/// Field names follow `#[serde(rename)]` and `#[serde(rename_all)]`, and `#[serde(skip)]` fields
+/// are left out.
+///
+/// `WsRequest::SCHEMA` is left empty, the schema is only known at runtime and available through
+/// `WsRequest::schema()`.
+#[proc_macro_derive(Endpoint, attributes(endpoint))]
+pub fn derive_endpoint(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_endpoint(input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+/// Derives `EndpointFields` and `EndpointType` for a struct, mapping it to `Type::Struct`.
+#[proc_macro_derive(EndpointStruct, attributes(endpoint))]
+pub fn derive_endpoint_struct(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_struct(input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+/// Derives `EndpointType` for a fieldless enum, mapping it to `Type::Enum`.
+///
+/// Variant values are taken from explicit discriminants, or count up from the previous one. Variant
+/// names follow `#[serde(rename)]` and `#[serde(rename_all)]`.
+#[proc_macro_derive(EndpointEnum, attributes(endpoint))]
+pub fn derive_endpoint_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_enum(input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+#[derive(Default)]
+struct EndpointArgs {
+    code: Option<LitInt>,
+    name: Option<LitStr>,
+    response: Option<Path>,
+    stream: Option<Path>,
+    description: Option<LitStr>,
+}
+
+fn parse_endpoint_args(attrs: &[Attribute]) -> syn::Result<EndpointArgs> {
+    let mut args = EndpointArgs::default();
+    for attr in attrs.iter().filter(|x| x.path().is_ident("endpoint")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("code") {
+                args.code = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("name") {
+                args.name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("response") {
+                args.response = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("stream") {
+                args.stream = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("description") {
+                args.description = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unknown endpoint attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(args)
+}
+
+fn parse_field_type_override(attrs: &[Attribute]) -> syn::Result<Option<Expr>> {
+    let mut ty = None;
+    for attr in attrs.iter().filter(|x| x.path().is_ident("endpoint")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("ty") {
+                ty = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown endpoint field attribute"))
+            }
+        })?;
+    }
+    Ok(ty)
+}
+
+#[derive(Default)]
+struct SerdeArgs {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    skip: bool,
+}
+
+/// Parses the `#[serde(...)]` options that change the serialized names, ignoring the others.
+fn parse_serde_args(attrs: &[Attribute]) -> syn::Result<SerdeArgs> {
+    let mut args = SerdeArgs::default();
+    for attr in attrs.iter().filter(|x| x.path().is_ident("serde")) {
+        for meta in attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)? {
+            let name = |meta: &Meta| -> syn::Result<Option<String>> {
+                match meta {
+                    Meta::NameValue(nv) => match &nv.value {
+                        Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) => Ok(Some(s.value())),
+                        value => Err(syn::Error::new_spanned(value, "expected a string")),
+                    },
+                    // `rename(serialize = "...", deserialize = "...")`
+                    Meta::List(list) => {
+                        let nested = list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+                        match nested.iter().find(|x| x.path().is_ident("serialize")) {
+                            Some(Meta::NameValue(nv)) => match &nv.value {
+                                Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) => Ok(Some(s.value())),
+                                value => Err(syn::Error::new_spanned(value, "expected a string")),
+                            },
+                            _ => Ok(None),
+                        }
+                    }
+                    Meta::Path(_) => Ok(None),
+                }
+            };
+            if meta.path().is_ident("rename") {
+                args.rename = name(&meta)?;
+            } else if meta.path().is_ident("rename_all") {
+                args.rename_all = name(&meta)?;
+            } else if meta.path().is_ident("skip") {
+                args.skip = true;
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Applies a serde `rename_all` rule to a field name, which is snake_case.
+fn rename_field(name: &str, rule: &str, span: &impl quote::ToTokens) -> syn::Result<String> {
+    let pascal = || {
+        name.split('_')
+            .map(|x| {
+                let mut chars = x.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            })
+            .collect::<String>()
+    };
+    Ok(match rule {
+        "lowercase" | "snake_case" => name.to_owned(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => name.to_ascii_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_lowercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        }
+        "kebab-case" => name.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => name.to_ascii_uppercase().replace('_', "-"),
+        _ => return Err(syn::Error::new_spanned(span, format!("unknown rename_all rule {}", rule))),
+    })
+}
+
+/// Applies a serde `rename_all` rule to a variant name, which is PascalCase.
+fn rename_variant(name: &str, rule: &str, span: &impl quote::ToTokens) -> syn::Result<String> {
+    let mut snake = String::new();
+    for (i, c) in name.char_indices() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    Ok(match rule {
+        "PascalCase" => name.to_owned(),
+        "lowercase" => name.to_ascii_lowercase(),
+        "UPPERCASE" => name.to_ascii_uppercase(),
+        _ => rename_field(&snake, rule, span)?,
+    })
+}
+
+fn doc_comment(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|x| x.path().is_ident("doc"))
+        .filter_map(|x| match &x.meta.require_name_value().ok()?.value {
+            Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) => Some(s.value().trim().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Expands to an expression building the `Vec<Field>` of a struct.
+fn expand_fields(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(input, "expected a struct"));
+    };
+    let fields = match &data.fields {
+        Fields::Named(fields) => fields.named.iter().collect::<Vec<_>>(),
+        Fields::Unit => vec![],
+        Fields::Unnamed(_) => return Err(syn::Error::new_spanned(input, "expected named fields")),
+    };
+    let rename_all = parse_serde_args(&input.attrs)?.rename_all;
+    let mut exprs = vec![];
+    for field in fields {
+        let serde = parse_serde_args(&field.attrs)?;
+        if serde.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().unwrap().to_string();
+        let ident = ident.strip_prefix("r#").unwrap_or(&ident);
+        let name = match (serde.rename, &rename_all) {
+            (Some(name), _) => name,
+            (None, Some(rule)) => rename_field(ident, rule, field)?,
+            (None, None) => ident.to_owned(),
+        };
+        let ty = &field.ty;
+        let ty = match parse_field_type_override(&field.attrs)? {
+            Some(expr) => quote! { #expr },
+            None => quote! { <#ty as ::endpoint_libs::model::EndpointType>::endpoint_type() },
+        };
+        exprs.push(quote! { ::endpoint_libs::model::Field::new(#name, #ty) });
+    }
+    Ok(quote! { vec![#(#exprs),*] })
+}
+
+fn expand_fields_impl(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let name = ident.to_string();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = expand_fields(input)?;
+    Ok(quote! {
+        impl #impl_generics ::endpoint_libs::model::EndpointFields for #ident #ty_generics #where_clause {
+            const NAME: &'static str = #name;
+
+            fn endpoint_fields() -> Vec<::endpoint_libs::model::Field> {
+                #fields
+            }
+        }
+    })
+}
+
+fn expand_endpoint(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let args = parse_endpoint_args(&input.attrs)?;
+    let code = args
+        .code
+        .ok_or_else(|| syn::Error::new(Span::call_site(), "missing #[endpoint(code = ...)]"))?;
+    let name = args
+        .name
+        .ok_or_else(|| syn::Error::new(Span::call_site(), "missing #[endpoint(name = \"...\")]"))?;
+    let ident = &input.ident;
+    if *ident != format!("{}Request", name.value()) {
+        return Err(syn::Error::new_spanned(
+            ident,
+            format!("request struct should be named {}Request", name.value()),
+        ));
+    }
+    let response = match args.response {
+        Some(response) => quote! { #response },
+        None => {
+            let response = format_ident!("{}Response", name.value());
+            quote! { #response }
+        }
+    };
+    let description = args
+        .description
+        .map(|x| x.value())
+        .unwrap_or_else(|| doc_comment(&input.attrs));
+    let stream_impl = args.stream.as_ref().map(|stream| {
+        quote! {
+            impl ::endpoint_libs::libs::ws::WsStreamRequest for #ident {
+                type Stream = #stream;
+            }
+        }
+    });
+    let stream = args.stream.map(|stream| {
+        quote! {
+            .with_stream_response_type(<#stream as ::endpoint_libs::model::EndpointType>::endpoint_type())
+        }
+    });
+    let fields_impl = expand_fields_impl(&input)?;
+
+    Ok(quote! {
+        #fields_impl
+
+        impl ::endpoint_libs::libs::ws::WsRequest for #ident {
+            type Response = #response;
+            const METHOD_ID: u32 = #code;
+            const SCHEMA: &'static str = "";
+
+            fn schema() -> ::endpoint_libs::model::EndpointSchema {
+                ::endpoint_libs::model::EndpointSchema::new(
+                    #name,
+                    #code,
+                    <Self as ::endpoint_libs::model::EndpointFields>::endpoint_fields(),
+                    <#response as ::endpoint_libs::model::EndpointFields>::endpoint_fields(),
+                )
+                #stream
+                .with_description(#description)
+            }
+        }
+
+        impl ::endpoint_libs::libs::ws::WsResponse for #response {
+            type Request = #ident;
+        }
+
+        #stream_impl
+    })
+}
+
+fn expand_struct(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields_impl = expand_fields_impl(&input)?;
+    Ok(quote! {
+        #fields_impl
+
+        impl #impl_generics ::endpoint_libs::model::EndpointType for #ident #ty_generics #where_clause {
+            fn endpoint_type() -> ::endpoint_libs::model::Type {
+                ::endpoint_libs::model::Type::struct_(
+                    <Self as ::endpoint_libs::model::EndpointFields>::NAME,
+                    <Self as ::endpoint_libs::model::EndpointFields>::endpoint_fields(),
+                )
+            }
+        }
+    })
+}
+
+fn expand_enum(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(&input, "expected an enum"));
+    };
+    let ident: &Ident = &input.ident;
+    let name = ident.to_string();
+    let rename_all = parse_serde_args(&input.attrs)?.rename_all;
+    let mut next_value = 0i64;
+    let mut variants = vec![];
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(variant, "expected a fieldless variant"));
+        }
+        let value = match &variant.discriminant {
+            Some((_, expr)) => discriminant_value(expr)?,
+            None => next_value,
+        };
+        next_value = value + 1;
+        let variant_name = variant_name(variant, rename_all.as_deref())?;
+        let comment = doc_comment(&variant.attrs);
+        variants.push(quote! {
+            ::endpoint_libs::model::EnumVariant::new_with_comment(#variant_name, #value, #comment)
+                .with_serialized_name()
+        });
+    }
+    Ok(quote! {
+        impl ::endpoint_libs::model::EndpointType for #ident {
+            fn endpoint_type() -> ::endpoint_libs::model::Type {
+                ::endpoint_libs::model::Type::enum_(#name, vec![#(#variants),*])
+            }
+        }
+    })
+}
+
+fn variant_name(variant: &Variant, rename_all: Option<&str>) -> syn::Result<String> {
+    let name = variant.ident.to_string();
+    match (parse_serde_args(&variant.attrs)?.rename, rename_all) {
+        (Some(name), _) => Ok(name),
+        (None, Some(rule)) => rename_variant(&name, rule, variant),
+        (None, None) => Ok(name),
+    }
+}
+
+fn discriminant_value(expr: &Expr) -> syn::Result<i64> {
+    match expr {
+        Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) => lit.base10_parse(),
+        Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_),
+            expr,
+            ..
+        }) => discriminant_value(expr).map(|x| -x),
+        _ => Err(syn::Error::new_spanned(expr, "expected an integer discriminant")),
+    }
+}
@@ -0,0 +1,8 @@
+pub mod database;
+pub mod rust;
+pub mod sql;
+pub mod typescript;
+pub use database::*;
+pub use rust::*;
+pub use sql::*;
+pub use typescript::*;
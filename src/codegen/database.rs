@@ -0,0 +1,214 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use convert_case::{Case, Casing};
+use eyre::{bail, Result};
+
+use crate::model::{Field, ProceduralFunction, Type};
+
+use super::rust::rust_type;
+use super::sql::{pg_param_name, pg_type, PG_FUNCTION_SCHEMA};
+
+/// Returns the name of the request struct of the function (e.g. `FunUserGetUserReq`).
+pub fn db_request_name(func: &ProceduralFunction) -> String {
+    format!("{}Req", func.name.to_case(Case::Pascal))
+}
+
+/// Returns the `SELECT` statement calling the function with named arguments, in parameter order.
+pub fn db_request_statement(func: &ProceduralFunction) -> String {
+    let args: Vec<String> = func
+        .parameters
+        .iter()
+        .enumerate()
+        .map(|(i, x)| format!("{} => ${}::{}", pg_param_name(&x.name), i + 1, pg_type(&x.ty)))
+        .collect();
+    format!("SELECT * FROM {}.{}({});", PG_FUNCTION_SCHEMA, func.name, args.join(", "))
+}
+
+/// Returns the Rust type of a function parameter or column. Values stored as `jsonb` are wrapped
+/// in `Jsonb`, the others implement `ToSql`/`FromSql` themselves.
+pub fn db_rust_type(ty: &Type) -> Result<String> {
+    Ok(match ty {
+        Type::Struct { .. } | Type::StructRef(_) | Type::DataTable { .. } | Type::DataTableIdentifer { .. } => {
+            format!("Jsonb<{}>", rust_type(ty))
+        }
+        Type::Vec(ty) => format!("Vec<{}>", db_rust_type(ty)?),
+        Type::Optional(ty) => format!("Option<{}>", db_rust_type(ty)?),
+        Type::Unit => bail!("Unit can't be a function parameter or column"),
+        ty => rust_type(ty),
+    })
+}
+
+fn write_struct(s: &mut String, derives: &str, name: &str, fields: &[Field]) -> Result<()> {
+    writeln!(s, "#[derive({})]", derives)?;
+    if fields.is_empty() {
+        writeln!(s, "pub struct {} {{}}", name)?;
+        return Ok(());
+    }
+    writeln!(s, "pub struct {} {{", name)?;
+    for field in fields {
+        writeln!(s, "    pub {}: {},", field.name.to_case(Case::Snake), db_rust_type(&field.ty)?)?;
+    }
+    writeln!(s, "}}")?;
+    Ok(())
+}
+
+/// Generates the request struct, its `DatabaseRequest` impl and the `FromRow` row struct of the function.
+pub fn gen_db_request(func: &ProceduralFunction) -> Result<String> {
+    let (row_name, row_fields) = match &func.return_row_type {
+        Type::Struct { name, fields } | Type::DataTable { name, fields } => (name.to_case(Case::Pascal), fields),
+        ty => bail!("Return row type of {} must be a struct, got {:?}", func.name, ty),
+    };
+    let request = db_request_name(func);
+    let mut s = String::new();
+    write_struct(
+        &mut s,
+        "Serialize, Deserialize, Debug, Clone",
+        &request,
+        &func.parameters,
+    )?;
+    writeln!(s)?;
+    write_struct(
+        &mut s,
+        "Serialize, Deserialize, Debug, Clone, FromRow",
+        &row_name,
+        row_fields,
+    )?;
+    writeln!(s)?;
+    writeln!(s, "impl DatabaseRequest for {} {{", request)?;
+    writeln!(s, "    type ResponseRow = {};", row_name)?;
+    writeln!(s, "    fn statement(&self) -> &str {{")?;
+    writeln!(s, "        \"{}\"", db_request_statement(func))?;
+    writeln!(s, "    }}")?;
+    writeln!(s, "    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {{")?;
+    let params: Vec<String> = func
+        .parameters
+        .iter()
+        .map(|x| format!("&self.{} as &(dyn ToSql + Sync)", x.name.to_case(Case::Snake)))
+        .collect();
+    writeln!(s, "        vec![{}]", params.join(", "))?;
+    writeln!(s, "    }}")?;
+    writeln!(s, "}}")?;
+    Ok(s)
+}
+
+/// Generates the database requests of all functions. Enums and structs referenced by the
+/// functions are expected to be in scope, e.g. from the models generated by `gen_rust_source`.
+pub fn gen_db_request_source(functions: &[ProceduralFunction]) -> Result<String> {
+    let mut s = String::new();
+    writeln!(s, "// This file is generated by endpoint-libs codegen, do not edit it manually.")?;
+    writeln!(s, "#[allow(unused_imports)]")?;
+    writeln!(s, "use endpoint_libs::libs::{{database::*, types::*}};")?;
+    writeln!(s, "use postgres_from_row::FromRow;")?;
+    writeln!(s, "#[allow(unused_imports)]")?;
+    writeln!(s, "use serde::*;")?;
+    for func in functions {
+        writeln!(s)?;
+        s.push_str(&gen_db_request(func)?);
+    }
+    Ok(s)
+}
+
+/// Generates the database requests of all functions and writes them to `path`.
+pub fn write_db_request_source(functions: &[ProceduralFunction], path: impl AsRef<Path>) -> Result<()> {
+    let source = gen_db_request_source(functions)?;
+    std::fs::write(path, source)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::rust::gen_rust_source;
+    use crate::codegen::sql::gen_pg_function;
+    use crate::model::{EndpointSchema, EnumVariant, Service};
+
+    // the fixtures are the generated sources below, compiled as part of the tests
+    #[allow(dead_code)]
+    mod models {
+        include!("fixtures/models.rs");
+    }
+
+    #[allow(dead_code)]
+    mod requests {
+        use super::models::*;
+        include!("fixtures/requests.rs");
+    }
+
+    /// One field of every `Type` but `Unit`, which can't be a parameter or column.
+    fn fields_of_every_type() -> Vec<Field> {
+        let kind = Type::enum_("fixture_kind", vec![EnumVariant::new("limit", 1), EnumVariant::new("market", 2)]);
+        vec![
+            Field::new("date", Type::Date),
+            Field::new("int", Type::Int),
+            Field::new("big_int", Type::BigInt),
+            Field::new("numeric", Type::Numeric),
+            Field::new("boolean", Type::Boolean),
+            Field::new("string", Type::String),
+            Field::new("bytea", Type::Bytea),
+            Field::new("uuid", Type::UUID),
+            Field::new("inet", Type::Inet),
+            Field::new("point", Type::struct_("fixture_point", vec![Field::new("x", Type::Int)])),
+            Field::new("point_ref", Type::struct_ref("fixture_point")),
+            Field::new("object", Type::Object),
+            Field::new("rows", Type::datatable("fixture_row", vec![Field::new("name", Type::String)])),
+            Field::new("rows_ref", Type::datatable_identifer("fixture_row")),
+            Field::new("points", Type::vec(Type::struct_ref("fixture_point"))),
+            Field::new("kinds", Type::vec(Type::enum_ref("fixture_kind"))),
+            Field::new("note", Type::optional(Type::String)),
+            Field::new("kind", kind),
+            Field::new("kind_ref", Type::optional(Type::enum_ref("fixture_kind"))),
+            Field::new("created_at", Type::TimeStampMs),
+            Field::new("updatedAt", Type::TimeStampMs),
+            Field::new("amount", Type::BlockchainDecimal),
+            Field::new("address", Type::BlockchainAddress),
+            Field::new("transaction_hash", Type::BlockchainTransactionHash),
+        ]
+    }
+
+    #[test]
+    fn test_gen_db_request() {
+        let func = ProceduralFunction::new(
+            "fun_user_get_user",
+            vec![
+                Field::new("name", Type::optional(Type::String)),
+                Field::new("user_id", Type::BigInt),
+            ],
+            vec![Field::new("user_id", Type::BigInt), Field::new("name", Type::String)],
+            "SELECT user_id, name FROM tbl.user WHERE user_id = a_user_id",
+        );
+        assert_eq!(
+            db_request_statement(&func),
+            "SELECT * FROM api.fun_user_get_user(a_user_id => $1::bigint, a_name => $2::varchar);"
+        );
+
+        let unit = ProceduralFunction::new("fun_user_unit", vec![Field::new("unit", Type::Unit)], vec![], "");
+        assert!(gen_db_request(&unit).is_err());
+    }
+
+    #[test]
+    fn test_gen_db_request_of_every_type() {
+        let service = Service::new(
+            "fixture",
+            1,
+            vec![EndpointSchema::new(
+                "FixtureEveryType",
+                10000,
+                fields_of_every_type(),
+                vec![Field::new("unit", Type::Unit)],
+            )],
+        );
+        let func = ProceduralFunction::new(
+            "fun_fixture_every_type",
+            fields_of_every_type(),
+            fields_of_every_type(),
+            "SELECT 1",
+        );
+        // the row struct reads the columns by their snake_case name
+        let sql = gen_pg_function(&func).unwrap();
+        assert!(sql.contains("    \"updated_at\" bigint"));
+        // the fixtures compile, so must the generated sources
+        assert_eq!(gen_rust_source(&[service]).unwrap(), include_str!("fixtures/models.rs"));
+        assert_eq!(gen_db_request_source(&[func]).unwrap(), include_str!("fixtures/requests.rs"));
+    }
+}
@@ -0,0 +1,75 @@
+// This file is generated by endpoint-libs codegen, do not edit it manually.
+#[allow(unused_imports)]
+use endpoint_libs::libs::{types::*, ws::*};
+#[allow(unused_imports)]
+use serde::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(postgres_types::ToSql, postgres_types::FromSql)]
+#[postgres(name = "enum_fixture_kind")]
+pub enum FixtureKind {
+    #[postgres(name = "limit")]
+    Limit = 1,
+    #[postgres(name = "market")]
+    Market = 2,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FixturePoint {
+    pub x: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureRow {
+    pub name: String,
+}
+
+// Service fixture (1)
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureEveryTypeRequest {
+    pub date: chrono::NaiveDate,
+    pub int: i32,
+    pub big_int: i64,
+    pub numeric: f64,
+    pub boolean: bool,
+    pub string: String,
+    pub bytea: Vec<u8>,
+    pub uuid: Uuid,
+    pub inet: std::net::IpAddr,
+    pub point: FixturePoint,
+    pub point_ref: FixturePoint,
+    pub object: serde_json::Value,
+    pub rows: Vec<FixtureRow>,
+    pub rows_ref: Vec<FixtureRow>,
+    pub points: Vec<FixturePoint>,
+    pub kinds: Vec<FixtureKind>,
+    #[serde(default)]
+    pub note: Option<String>,
+    pub kind: FixtureKind,
+    #[serde(default)]
+    pub kind_ref: Option<FixtureKind>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub amount: rust_decimal::Decimal,
+    pub address: BlockchainAddress,
+    pub transaction_hash: BlockchainTransactionHash,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureEveryTypeResponse {
+    pub unit: (),
+}
+
+impl WsRequest for FixtureEveryTypeRequest {
+    type Response = FixtureEveryTypeResponse;
+    const METHOD_ID: u32 = 10000;
+    const SCHEMA: &'static str = "{\"name\":\"FixtureEveryType\",\"code\":10000,\"parameters\":[{\"name\":\"date\",\"ty\":\"Date\"},{\"name\":\"int\",\"ty\":\"Int\"},{\"name\":\"big_int\",\"ty\":\"BigInt\"},{\"name\":\"numeric\",\"ty\":\"Numeric\"},{\"name\":\"boolean\",\"ty\":\"Boolean\"},{\"name\":\"string\",\"ty\":\"String\"},{\"name\":\"bytea\",\"ty\":\"Bytea\"},{\"name\":\"uuid\",\"ty\":\"UUID\"},{\"name\":\"inet\",\"ty\":\"Inet\"},{\"name\":\"point\",\"ty\":{\"Struct\":{\"name\":\"fixture_point\",\"fields\":[{\"name\":\"x\",\"ty\":\"Int\"}]}}},{\"name\":\"point_ref\",\"ty\":{\"StructRef\":\"fixture_point\"}},{\"name\":\"object\",\"ty\":\"Object\"},{\"name\":\"rows\",\"ty\":{\"DataTable\":{\"name\":\"fixture_row\",\"fields\":[{\"name\":\"name\",\"ty\":\"String\"}]}}},{\"name\":\"rows_ref\",\"ty\":{\"DataTableIdentifer\":{\"name\":\"fixture_row\"}}},{\"name\":\"points\",\"ty\":{\"Vec\":{\"StructRef\":\"fixture_point\"}}},{\"name\":\"kinds\",\"ty\":{\"Vec\":{\"EnumRef\":\"fixture_kind\"}}},{\"name\":\"note\",\"ty\":{\"Optional\":\"String\"}},{\"name\":\"kind\",\"ty\":{\"Enum\":{\"name\":\"fixture_kind\",\"variants\":[{\"name\":\"limit\",\"value\":1,\"comment\":\"\"},{\"name\":\"market\",\"value\":2,\"comment\":\"\"}]}}},{\"name\":\"kind_ref\",\"ty\":{\"Optional\":{\"EnumRef\":\"fixture_kind\"}}},{\"name\":\"created_at\",\"ty\":\"TimeStampMs\"},{\"name\":\"updatedAt\",\"ty\":\"TimeStampMs\"},{\"name\":\"amount\",\"ty\":\"BlockchainDecimal\"},{\"name\":\"address\",\"ty\":\"BlockchainAddress\"},{\"name\":\"transaction_hash\",\"ty\":\"BlockchainTransactionHash\"}],\"returns\":[{\"name\":\"unit\",\"ty\":\"Unit\"}],\"stream_response\":null,\"description\":\"\",\"json_schema\":null}";
+}
+impl WsResponse for FixtureEveryTypeResponse {
+    type Request = FixtureEveryTypeRequest;
+}
@@ -0,0 +1,72 @@
+// This file is generated by endpoint-libs codegen, do not edit it manually.
+#[allow(unused_imports)]
+use endpoint_libs::libs::{database::*, types::*};
+use postgres_from_row::FromRow;
+#[allow(unused_imports)]
+use serde::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FunFixtureEveryTypeReq {
+    pub date: chrono::NaiveDate,
+    pub int: i32,
+    pub big_int: i64,
+    pub numeric: f64,
+    pub boolean: bool,
+    pub string: String,
+    pub bytea: Vec<u8>,
+    pub uuid: Uuid,
+    pub inet: std::net::IpAddr,
+    pub point: Jsonb<FixturePoint>,
+    pub point_ref: Jsonb<FixturePoint>,
+    pub object: serde_json::Value,
+    pub rows: Jsonb<Vec<FixtureRow>>,
+    pub rows_ref: Jsonb<Vec<FixtureRow>>,
+    pub points: Vec<Jsonb<FixturePoint>>,
+    pub kinds: Vec<FixtureKind>,
+    pub kind: FixtureKind,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub amount: rust_decimal::Decimal,
+    pub address: BlockchainAddress,
+    pub transaction_hash: BlockchainTransactionHash,
+    pub note: Option<String>,
+    pub kind_ref: Option<FixtureKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct FunFixtureEveryTypeRespRow {
+    pub date: chrono::NaiveDate,
+    pub int: i32,
+    pub big_int: i64,
+    pub numeric: f64,
+    pub boolean: bool,
+    pub string: String,
+    pub bytea: Vec<u8>,
+    pub uuid: Uuid,
+    pub inet: std::net::IpAddr,
+    pub point: Jsonb<FixturePoint>,
+    pub point_ref: Jsonb<FixturePoint>,
+    pub object: serde_json::Value,
+    pub rows: Jsonb<Vec<FixtureRow>>,
+    pub rows_ref: Jsonb<Vec<FixtureRow>>,
+    pub points: Vec<Jsonb<FixturePoint>>,
+    pub kinds: Vec<FixtureKind>,
+    pub note: Option<String>,
+    pub kind: FixtureKind,
+    pub kind_ref: Option<FixtureKind>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub amount: rust_decimal::Decimal,
+    pub address: BlockchainAddress,
+    pub transaction_hash: BlockchainTransactionHash,
+}
+
+impl DatabaseRequest for FunFixtureEveryTypeReq {
+    type ResponseRow = FunFixtureEveryTypeRespRow;
+    fn statement(&self) -> &str {
+        "SELECT * FROM api.fun_fixture_every_type(a_date => $1::date, a_int => $2::int, a_big_int => $3::bigint, a_numeric => $4::double precision, a_boolean => $5::boolean, a_string => $6::varchar, a_bytea => $7::bytea, a_uuid => $8::uuid, a_inet => $9::inet, a_point => $10::jsonb, a_point_ref => $11::jsonb, a_object => $12::jsonb, a_rows => $13::jsonb, a_rows_ref => $14::jsonb, a_points => $15::jsonb[], a_kinds => $16::enum_fixture_kind[], a_kind => $17::enum_fixture_kind, a_created_at => $18::bigint, a_updated_at => $19::bigint, a_amount => $20::decimal(56, 18), a_address => $21::varchar(42), a_transaction_hash => $22::varchar(66), a_note => $23::varchar, a_kind_ref => $24::enum_fixture_kind);"
+    }
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        vec![&self.date as &(dyn ToSql + Sync), &self.int as &(dyn ToSql + Sync), &self.big_int as &(dyn ToSql + Sync), &self.numeric as &(dyn ToSql + Sync), &self.boolean as &(dyn ToSql + Sync), &self.string as &(dyn ToSql + Sync), &self.bytea as &(dyn ToSql + Sync), &self.uuid as &(dyn ToSql + Sync), &self.inet as &(dyn ToSql + Sync), &self.point as &(dyn ToSql + Sync), &self.point_ref as &(dyn ToSql + Sync), &self.object as &(dyn ToSql + Sync), &self.rows as &(dyn ToSql + Sync), &self.rows_ref as &(dyn ToSql + Sync), &self.points as &(dyn ToSql + Sync), &self.kinds as &(dyn ToSql + Sync), &self.kind as &(dyn ToSql + Sync), &self.created_at as &(dyn ToSql + Sync), &self.updated_at as &(dyn ToSql + Sync), &self.amount as &(dyn ToSql + Sync), &self.address as &(dyn ToSql + Sync), &self.transaction_hash as &(dyn ToSql + Sync), &self.note as &(dyn ToSql + Sync), &self.kind_ref as &(dyn ToSql + Sync)]
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use convert_case::{Case, Casing};
+use eyre::{bail, Result};
+
+use crate::model::{EndpointSchema, EnumVariant, Field, Service, Type};
+
+use super::sql::pg_enum_name;
+
+/// Returns the Rust type of the given `Type` as it appears in generated code.
+pub fn rust_type(ty: &Type) -> String {
+    match ty {
+        Type::Date => "chrono::NaiveDate".to_owned(),
+        Type::Int => "i32".to_owned(),
+        Type::BigInt => "i64".to_owned(),
+        Type::Numeric => "f64".to_owned(),
+        Type::Boolean => "bool".to_owned(),
+        Type::String => "String".to_owned(),
+        Type::Bytea => "Vec<u8>".to_owned(),
+        Type::UUID => "Uuid".to_owned(),
+        Type::Inet => "std::net::IpAddr".to_owned(),
+        Type::Struct { name, .. } => name.to_case(Case::Pascal),
+        Type::StructRef(name) => name.to_case(Case::Pascal),
+        Type::Object => "serde_json::Value".to_owned(),
+        Type::DataTable { name, .. } => format!("Vec<{}>", name.to_case(Case::Pascal)),
+        Type::DataTableIdentifer { name } => format!("Vec<{}>", name.to_case(Case::Pascal)),
+        Type::Vec(ty) => format!("Vec<{}>", rust_type(ty)),
+        Type::Unit => "()".to_owned(),
+        Type::Optional(ty) => format!("Option<{}>", rust_type(ty)),
+        Type::Enum { name, .. } => name.to_case(Case::Pascal),
+        Type::EnumRef(name) => name.to_case(Case::Pascal),
+        Type::TimeStampMs => "i64".to_owned(),
+        Type::BlockchainDecimal => "rust_decimal::Decimal".to_owned(),
+        Type::BlockchainAddress => "BlockchainAddress".to_owned(),
+        Type::BlockchainTransactionHash => "BlockchainTransactionHash".to_owned(),
+    }
+}
+
+/// A named type that gets its own definition in the generated code.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum NamedType {
+    Struct(Vec<Field>),
+    Enum(Vec<EnumVariant>),
+}
+
+/// Collects the structs, datatable rows and enums declared inline in `ty`.
+fn collect_named_types(ty: &Type, types: &mut BTreeMap<String, NamedType>) -> Result<()> {
+    let (name, named) = match ty {
+        Type::Struct { name, fields } | Type::DataTable { name, fields } => {
+            for field in fields {
+                collect_named_types(&field.ty, types)?;
+            }
+            (name, NamedType::Struct(fields.clone()))
+        }
+        Type::Enum { name, variants } => (name, NamedType::Enum(variants.clone())),
+        Type::Vec(ty) | Type::Optional(ty) => return collect_named_types(ty, types),
+        _ => return Ok(()),
+    };
+    let name = name.to_case(Case::Pascal);
+    match types.get(&name) {
+        Some(existing) if *existing != named => bail!("Conflicting definitions of type {}", name),
+        Some(_) => {}
+        None => {
+            types.insert(name, named);
+        }
+    }
+    Ok(())
+}
+
+/// Collects the named types used by all endpoints of the given services, keyed by Pascal case name.
+pub(super) fn collect_service_types(services: &[Service]) -> Result<BTreeMap<String, NamedType>> {
+    let mut types = BTreeMap::new();
+    for endpoint in services.iter().flat_map(|x| &x.endpoints) {
+        for field in endpoint.parameters.iter().chain(&endpoint.returns) {
+            collect_named_types(&field.ty, &mut types)?;
+        }
+        if let Some(ty) = &endpoint.stream_response {
+            collect_named_types(ty, &mut types)?;
+        }
+    }
+    Ok(types)
+}
+
+fn write_doc(s: &mut String, indent: &str, doc: &str) -> Result<()> {
+    for line in doc.lines().filter(|x| !x.trim().is_empty()) {
+        writeln!(s, "{}/// {}", indent, line.trim())?;
+    }
+    Ok(())
+}
+
+fn write_struct(s: &mut String, name: &str, fields: &[Field]) -> Result<()> {
+    writeln!(s, "#[derive(Serialize, Deserialize, Debug, Clone)]")?;
+    writeln!(s, "#[serde(rename_all = \"camelCase\")]")?;
+    if fields.is_empty() {
+        writeln!(s, "pub struct {} {{}}", name)?;
+        return Ok(());
+    }
+    writeln!(s, "pub struct {} {{", name)?;
+    for field in fields {
+        if matches!(field.ty, Type::Optional(_)) {
+            writeln!(s, "    #[serde(default)]")?;
+        }
+        writeln!(s, "    pub {}: {},", field.name.to_case(Case::Snake), rust_type(&field.ty))?;
+    }
+    writeln!(s, "}}")?;
+    Ok(())
+}
+
+/// Enums also map to the Postgres enum generated by `gen_pg_enum`, whose labels are snake_case.
+fn write_enum(s: &mut String, name: &str, variants: &[EnumVariant]) -> Result<()> {
+    writeln!(s, "#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]")?;
+    writeln!(s, "#[derive(postgres_types::ToSql, postgres_types::FromSql)]")?;
+    writeln!(s, "#[postgres(name = \"{}\")]", pg_enum_name(name))?;
+    writeln!(s, "pub enum {} {{", name)?;
+    for variant in variants {
+        write_doc(s, "    ", &variant.comment)?;
+        writeln!(s, "    #[postgres(name = \"{}\")]", variant.name.to_case(Case::Snake))?;
+        writeln!(s, "    {} = {},", variant.name.to_case(Case::Pascal), variant.value)?;
+    }
+    writeln!(s, "}}")?;
+    Ok(())
+}
+
+fn write_endpoint(s: &mut String, endpoint: &EndpointSchema) -> Result<()> {
+    let name = endpoint.name.to_case(Case::Pascal);
+    let request = format!("{}Request", name);
+    let response = format!("{}Response", name);
+    write_doc(s, "", &endpoint.description)?;
+    write_struct(s, &request, &endpoint.parameters)?;
+    writeln!(s)?;
+    write_struct(s, &response, &endpoint.returns)?;
+    writeln!(s)?;
+    let schema = serde_json::to_string(endpoint)?;
+    writeln!(s, "impl WsRequest for {} {{", request)?;
+    writeln!(s, "    type Response = {};", response)?;
+    writeln!(s, "    const METHOD_ID: u32 = {};", endpoint.code)?;
+    // `Debug` escapes the string as a Rust literal, the description may contain any character
+    writeln!(s, "    const SCHEMA: &'static str = {:?};", schema)?;
+    writeln!(s, "}}")?;
+    writeln!(s, "impl WsResponse for {} {{", response)?;
+    writeln!(s, "    type Request = {};", request)?;
+    writeln!(s, "}}")?;
+    Ok(())
+}
+
+/// Generates the Rust source of the given services: enums, structs and datatable rows, then the
+/// `{Name}Request`/`{Name}Response` pairs and their `WsRequest`/`WsResponse` impls.
+///
+/// The generated enums derive `ToSql`/`FromSql`, the crate including them needs a dependency on
+/// `postgres-types` with the `derive` feature.
+pub fn gen_rust_source(services: &[Service]) -> Result<String> {
+    let types = collect_service_types(services)?;
+
+    let mut s = String::new();
+    writeln!(s, "// This file is generated by endpoint-libs codegen, do not edit it manually.")?;
+    writeln!(s, "#[allow(unused_imports)]")?;
+    writeln!(s, "use endpoint_libs::libs::{{types::*, ws::*}};")?;
+    writeln!(s, "#[allow(unused_imports)]")?;
+    writeln!(s, "use serde::*;")?;
+    for (name, ty) in &types {
+        writeln!(s)?;
+        match ty {
+            NamedType::Struct(fields) => write_struct(&mut s, name, fields)?,
+            NamedType::Enum(variants) => write_enum(&mut s, name, variants)?,
+        }
+    }
+    for service in services {
+        writeln!(s)?;
+        writeln!(s, "// Service {} ({})", service.name, service.id)?;
+        for endpoint in &service.endpoints {
+            writeln!(s)?;
+            write_endpoint(&mut s, endpoint)?;
+        }
+    }
+    Ok(s)
+}
+
+/// Generates the Rust source of the given services and writes it to `path`.
+pub fn write_rust_source(services: &[Service], path: impl AsRef<Path>) -> Result<()> {
+    let source = gen_rust_source(services)?;
+    std::fs::write(path, source)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gen_rust_source() {
+        let side = Type::enum_("side", vec![EnumVariant::new("buy", 1), EnumVariant::new("sell", 2)]);
+        let service = Service::new(
+            "user",
+            1,
+            vec![EndpointSchema::new(
+                "UserListSymbols",
+                10020,
+                vec![Field::new("user_id", Type::BigInt), Field::new("side", Type::optional(side))],
+                vec![Field::new(
+                    "data",
+                    Type::datatable("UserSymbol", vec![Field::new("symbol", Type::String)]),
+                )],
+            )
+            .with_description("Lists \"#symbols\"#")],
+        );
+        let source = gen_rust_source(&[service]).unwrap();
+        assert!(source.contains("#[postgres(name = \"enum_side\")]\npub enum Side {\n"));
+        assert!(source.contains("    #[postgres(name = \"buy\")]\n    Buy = 1,\n"));
+        assert!(source.contains("    #[postgres(name = \"sell\")]\n    Sell = 2,\n}"));
+        assert!(source.contains("pub struct UserSymbol {\n    pub symbol: String,\n}"));
+        assert!(source.contains("    pub user_id: i64,\n    #[serde(default)]\n    pub side: Option<Side>,"));
+        assert!(source.contains("pub struct UserListSymbolsResponse {\n    pub data: Vec<UserSymbol>,\n}"));
+        assert!(source.contains("    const METHOD_ID: u32 = 10020;"));
+        assert!(source.contains(r##"\"description\":\"Lists \\\"#symbols\\\"#\""##));
+        assert!(source.contains("impl WsResponse for UserListSymbolsResponse {"));
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use convert_case::{Case, Casing};
+use eyre::{bail, Result};
+
+use crate::model::{EnumVariant, ProceduralFunction, Type};
+
+/// The schema the generated functions are created in.
+pub const PG_FUNCTION_SCHEMA: &str = "api";
+
+/// Returns the name of the Postgres enum type of a `Type::Enum` (e.g. `enum_side`).
+pub fn pg_enum_name(name: &str) -> String {
+    format!("enum_{}", name.to_case(Case::Snake))
+}
+
+/// Returns the name of a generated function parameter, prefixed to avoid clashing with the
+/// columns of the returned table (e.g. `a_user_id`).
+pub fn pg_param_name(name: &str) -> String {
+    format!("a_{}", name.to_case(Case::Snake))
+}
+
+/// Returns the Postgres type of the given `Type`.
+pub fn pg_type(ty: &Type) -> String {
+    match ty {
+        Type::Date => "date".to_owned(),
+        Type::Int => "int".to_owned(),
+        Type::BigInt => "bigint".to_owned(),
+        Type::Numeric => "double precision".to_owned(),
+        Type::Boolean => "boolean".to_owned(),
+        Type::String => "varchar".to_owned(),
+        Type::Bytea => "bytea".to_owned(),
+        Type::UUID => "uuid".to_owned(),
+        Type::Inet => "inet".to_owned(),
+        Type::Struct { .. } | Type::StructRef(_) | Type::Object => "jsonb".to_owned(),
+        Type::DataTable { .. } | Type::DataTableIdentifer { .. } => "jsonb".to_owned(),
+        Type::Vec(ty) => format!("{}[]", pg_type(ty)),
+        Type::Unit => "void".to_owned(),
+        Type::Optional(ty) => pg_type(ty),
+        Type::Enum { name, .. } => pg_enum_name(name),
+        Type::EnumRef(name) => pg_enum_name(name),
+        Type::TimeStampMs => "bigint".to_owned(),
+        Type::BlockchainDecimal => "decimal(56, 18)".to_owned(),
+        Type::BlockchainAddress => "varchar(42)".to_owned(),
+        Type::BlockchainTransactionHash => "varchar(66)".to_owned(),
+    }
+}
+
+fn quote_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Generates an idempotent `CREATE TYPE ... AS ENUM` statement for the enum.
+pub fn gen_pg_enum(name: &str, variants: &[EnumVariant]) -> Result<String> {
+    let mut s = String::new();
+    let values: Vec<String> = variants
+        .iter()
+        .map(|x| quote_literal(&x.name.to_case(Case::Snake)))
+        .collect();
+    writeln!(s, "DO $$")?;
+    writeln!(s, "BEGIN")?;
+    writeln!(s, "    CREATE TYPE {} AS ENUM ({});", pg_enum_name(name), values.join(", "))?;
+    writeln!(s, "EXCEPTION WHEN duplicate_object THEN NULL;")?;
+    writeln!(s, "END")?;
+    writeln!(s, "$$;")?;
+    Ok(s)
+}
+
+/// Generates the `CREATE OR REPLACE FUNCTION` statement of the procedural function.
+/// Bodies starting with `BEGIN` or `DECLARE` are written as PL/pgSQL, other bodies as plain SQL.
+pub fn gen_pg_function(func: &ProceduralFunction) -> Result<String> {
+    let mut s = String::new();
+    let params: Vec<String> = func
+        .parameters
+        .iter()
+        .map(|x| match &x.ty {
+            Type::Optional(_) => format!("{} {} DEFAULT NULL", pg_param_name(&x.name), pg_type(&x.ty)),
+            ty => format!("{} {}", pg_param_name(&x.name), pg_type(ty)),
+        })
+        .collect();
+    writeln!(
+        s,
+        "CREATE OR REPLACE FUNCTION {}.{}({})",
+        PG_FUNCTION_SCHEMA,
+        func.name,
+        params.join(", ")
+    )?;
+    match &func.return_row_type {
+        Type::Struct { fields, .. } | Type::DataTable { fields, .. } if fields.is_empty() => {
+            writeln!(s, "RETURNS void")?;
+        }
+        Type::Struct { fields, .. } | Type::DataTable { fields, .. } => {
+            writeln!(s, "RETURNS TABLE (")?;
+            let columns: Vec<String> = fields
+                .iter()
+                // the columns are read by the snake_case fields of the generated row struct
+                .map(|x| format!("    \"{}\" {}", x.name.to_case(Case::Snake), pg_type(&x.ty)))
+                .collect();
+            writeln!(s, "{}", columns.join(",\n"))?;
+            writeln!(s, ")")?;
+        }
+        Type::StructRef(name) => bail!("Return row type of {} must be inlined, got StructRef({})", func.name, name),
+        ty => writeln!(s, "RETURNS {}", pg_type(ty))?,
+    }
+    let body = func.body.trim();
+    let upper = body.to_ascii_uppercase();
+    let language = if upper.starts_with("BEGIN") || upper.starts_with("DECLARE") {
+        "plpgsql"
+    } else {
+        "sql"
+    };
+    writeln!(s, "LANGUAGE {}", language)?;
+    writeln!(s, "AS $$")?;
+    writeln!(s, "{}", body)?;
+    writeln!(s, "$$;")?;
+    Ok(s)
+}
+
+fn collect_enums(ty: &Type, enums: &mut BTreeMap<String, Vec<EnumVariant>>) {
+    match ty {
+        Type::Enum { name, variants } => {
+            enums.entry(name.clone()).or_insert_with(|| variants.clone());
+        }
+        Type::Struct { fields, .. } | Type::DataTable { fields, .. } => {
+            for field in fields {
+                collect_enums(&field.ty, enums);
+            }
+        }
+        Type::Vec(ty) | Type::Optional(ty) => collect_enums(ty, enums),
+        _ => {}
+    }
+}
+
+/// Generates a migration script creating the enums used by the functions, then the functions.
+pub fn gen_pg_sql(functions: &[ProceduralFunction]) -> Result<String> {
+    let mut enums = BTreeMap::new();
+    for func in functions {
+        for field in &func.parameters {
+            collect_enums(&field.ty, &mut enums);
+        }
+        collect_enums(&func.return_row_type, &mut enums);
+    }
+    let mut s = String::new();
+    writeln!(s, "-- This file is generated by endpoint-libs codegen, do not edit it manually.")?;
+    writeln!(s, "CREATE SCHEMA IF NOT EXISTS {};", PG_FUNCTION_SCHEMA)?;
+    for (name, variants) in &enums {
+        writeln!(s)?;
+        s.push_str(&gen_pg_enum(name, variants)?);
+    }
+    for func in functions {
+        writeln!(s)?;
+        s.push_str(&gen_pg_function(func)?);
+    }
+    Ok(s)
+}
+
+/// Generates the migration script of the functions and writes it to `path`.
+pub fn write_pg_sql(functions: &[ProceduralFunction], path: impl AsRef<Path>) -> Result<()> {
+    let sql = gen_pg_sql(functions)?;
+    std::fs::write(path, sql)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Field;
+
+    #[test]
+    fn test_gen_pg_function() {
+        let side = Type::enum_("side", vec![EnumVariant::new("buy", 1), EnumVariant::new("sell", 2)]);
+        let func = ProceduralFunction::new(
+            "fun_user_list_orders",
+            vec![
+                Field::new("symbol", Type::optional(Type::String)),
+                Field::new("user_id", Type::BigInt),
+                Field::new("side", side),
+            ],
+            vec![Field::new("order_id", Type::BigInt), Field::new("price", Type::BlockchainDecimal)],
+            "SELECT order_id, price FROM tbl.order WHERE user_id = a_user_id",
+        );
+        let sql = gen_pg_function(&func).unwrap();
+        assert_eq!(
+            sql,
+            "CREATE OR REPLACE FUNCTION api.fun_user_list_orders(a_user_id bigint, a_side enum_side, a_symbol varchar DEFAULT NULL)
+RETURNS TABLE (
+    \"order_id\" bigint,
+    \"price\" decimal(56, 18)
+)
+LANGUAGE sql
+AS $$
+SELECT order_id, price FROM tbl.order WHERE user_id = a_user_id
+$$;
+"
+        );
+        let sql = gen_pg_sql(&[func]).unwrap();
+        assert!(sql.contains("    CREATE TYPE enum_side AS ENUM ('buy', 'sell');\n"));
+    }
+}
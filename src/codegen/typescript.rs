@@ -0,0 +1,293 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use convert_case::{Case, Casing};
+use eyre::Result;
+
+use crate::model::{EndpointSchema, EnumVariant, Field, Service, Type};
+
+use super::rust::{collect_service_types, NamedType};
+
+/// Returns the TypeScript type of the given `Type`, matching the JSON produced by the Rust side.
+///
+/// `BigInt` and `TimeStampMs` are `number` like the other integers, so values beyond 2^53 - 1 lose
+/// precision once parsed, which the generated file notes in its header.
+pub fn typescript_type(ty: &Type) -> String {
+    match ty {
+        Type::Date => "string".to_owned(),
+        Type::Int | Type::BigInt | Type::Numeric | Type::TimeStampMs => "number".to_owned(),
+        Type::Boolean => "boolean".to_owned(),
+        Type::String | Type::Bytea | Type::UUID | Type::Inet => "string".to_owned(),
+        Type::Struct { name, .. } => name.to_case(Case::Pascal),
+        Type::StructRef(name) => name.to_case(Case::Pascal),
+        Type::Object => "any".to_owned(),
+        Type::DataTable { name, .. } => format!("{}[]", name.to_case(Case::Pascal)),
+        Type::DataTableIdentifer { name } => format!("{}[]", name.to_case(Case::Pascal)),
+        Type::Vec(ty) => match **ty {
+            Type::Optional(_) => format!("({})[]", typescript_type(ty)),
+            _ => format!("{}[]", typescript_type(ty)),
+        },
+        Type::Unit => "null".to_owned(),
+        Type::Optional(ty) => format!("{} | null", typescript_type(ty)),
+        Type::Enum { name, .. } => name.to_case(Case::Pascal),
+        Type::EnumRef(name) => name.to_case(Case::Pascal),
+        // decimals, addresses and hashes are serialized as strings to keep their precision
+        Type::BlockchainDecimal => "string".to_owned(),
+        Type::BlockchainAddress => "string".to_owned(),
+        Type::BlockchainTransactionHash => "string".to_owned(),
+    }
+}
+
+fn write_doc(s: &mut String, indent: &str, doc: &str) -> Result<()> {
+    let lines: Vec<&str> = doc.lines().map(|x| x.trim()).filter(|x| !x.is_empty()).collect();
+    if lines.is_empty() {
+        return Ok(());
+    }
+    writeln!(s, "{}/**", indent)?;
+    for line in lines {
+        writeln!(s, "{} * {}", indent, line)?;
+    }
+    writeln!(s, "{} */", indent)?;
+    Ok(())
+}
+
+fn write_interface(s: &mut String, name: &str, fields: &[Field]) -> Result<()> {
+    writeln!(s, "export interface {} {{", name)?;
+    for field in fields {
+        let name = field.name.to_case(Case::Camel);
+        match &field.ty {
+            Type::Optional(ty) => writeln!(s, "  {}?: {} | null;", name, typescript_type(ty))?,
+            ty => writeln!(s, "  {}: {};", name, typescript_type(ty))?,
+        }
+    }
+    writeln!(s, "}}")?;
+    Ok(())
+}
+
+fn write_enum(s: &mut String, name: &str, variants: &[EnumVariant]) -> Result<()> {
+    writeln!(s, "export enum {} {{", name)?;
+    for variant in variants {
+        write_doc(s, "  ", &variant.comment)?;
+        let variant = variant.name.to_case(Case::Pascal);
+        writeln!(s, "  {} = \"{}\",", variant, variant)?;
+    }
+    writeln!(s, "}}")?;
+    Ok(())
+}
+
+const PROTOCOL: &str = r#"export interface WsStreamResponse<T = any> {
+  original_seq: number;
+  method: number;
+  stream_seq: number;
+  stream_code: number;
+  data: T;
+}
+
+export type WsResponse =
+  | { type: "Immediate"; method: number; seq: number; params: any }
+  | ({ type: "Stream" } & WsStreamResponse)
+  | { type: "Error"; method: number; code: number; seq: number; log_id: string; params: any }
+  | { type: "Log"; seq: number; log_id: number; level: string; message: string }
+  | { type: "Forwarded"; method: number; seq: number }
+  | { type: "Close" };
+
+export type StreamHandler<T> = (data: T, response: WsStreamResponse<T>) => void;
+
+export class WsError extends Error {
+  constructor(
+    public readonly method: number,
+    public readonly code: number,
+    public readonly logId: string,
+    public readonly params: any,
+  ) {
+    super(`Error ${code} on method ${method}: ${JSON.stringify(params)}`);
+  }
+}
+
+interface PendingRequest {
+  resolve: (params: any) => void;
+  reject: (err: Error) => void;
+}
+
+export class BaseClient {
+  private seq = 0;
+  private readonly pending = new Map<number, PendingRequest>();
+  private readonly streams = new Map<number, StreamHandler<any>>();
+
+  constructor(private readonly socket: WebSocket) {
+    socket.addEventListener("message", (event) => this.onMessage(event.data));
+    socket.addEventListener("close", () => this.onClose());
+  }
+
+  protected request<P, R>(method: number, params: P, onStream?: StreamHandler<any>): Promise<R> {
+    const seq = ++this.seq;
+    if (onStream) {
+      this.streams.set(seq, onStream);
+    }
+    return new Promise<R>((resolve, reject) => {
+      this.pending.set(seq, { resolve, reject });
+      this.socket.send(JSON.stringify({ method, seq, params }));
+    });
+  }
+
+  unsubscribe(seq: number): void {
+    this.streams.delete(seq);
+  }
+
+  close(): void {
+    this.socket.close();
+  }
+
+  private onMessage(raw: string): void {
+    const resp: WsResponse = JSON.parse(raw);
+    switch (resp.type) {
+      case "Immediate":
+        this.pending.get(resp.seq)?.resolve(resp.params);
+        this.pending.delete(resp.seq);
+        break;
+      case "Stream":
+        this.streams.get(resp.original_seq)?.(resp.data, resp);
+        break;
+      case "Error":
+        this.pending.get(resp.seq)?.reject(new WsError(resp.method, resp.code, resp.log_id, resp.params));
+        this.pending.delete(resp.seq);
+        this.streams.delete(resp.seq);
+        break;
+      case "Close":
+        this.close();
+        break;
+      default:
+        break;
+    }
+  }
+
+  private onClose(): void {
+    for (const pending of this.pending.values()) {
+      pending.reject(new Error("Connection closed"));
+    }
+    this.pending.clear();
+    this.streams.clear();
+  }
+}
+"#;
+
+fn write_client_method(s: &mut String, endpoint: &EndpointSchema) -> Result<()> {
+    let name = endpoint.name.to_case(Case::Pascal);
+    let method = endpoint.name.to_case(Case::Camel);
+    writeln!(s)?;
+    write_doc(s, "  ", &endpoint.description)?;
+    match &endpoint.stream_response {
+        Some(_) => {
+            writeln!(
+                s,
+                "  {}(params: {name}Request, onStream: {name}StreamHandler): Promise<{name}Response> {{",
+                method
+            )?;
+            writeln!(s, "    return this.request({}, params, onStream);", endpoint.code)?;
+        }
+        None => {
+            writeln!(s, "  {}(params: {name}Request): Promise<{name}Response> {{", method)?;
+            writeln!(s, "    return this.request({}, params);", endpoint.code)?;
+        }
+    }
+    writeln!(s, "  }}")?;
+    Ok(())
+}
+
+/// Generates the TypeScript SDK of the given services: interfaces for every request, response
+/// and named type, stream handler typings and a client class with one method per endpoint.
+pub fn gen_typescript_source(services: &[Service]) -> Result<String> {
+    let types = collect_service_types(services)?;
+
+    let mut s = String::new();
+    writeln!(s, "// This file is generated by endpoint-libs codegen, do not edit it manually.")?;
+    writeln!(s, "//")?;
+    writeln!(s, "// 64-bit integers (e.g. ids and timestamps) are typed as `number`, as JSON.parse returns them:")?;
+    writeln!(s, "// values beyond Number.MAX_SAFE_INTEGER (2^53 - 1) lose precision.")?;
+    writeln!(s)?;
+    s.push_str(PROTOCOL);
+    for (name, ty) in &types {
+        writeln!(s)?;
+        match ty {
+            NamedType::Struct(fields) => write_interface(&mut s, name, fields)?,
+            NamedType::Enum(variants) => write_enum(&mut s, name, variants)?,
+        }
+    }
+    for endpoint in services.iter().flat_map(|x| &x.endpoints) {
+        let name = endpoint.name.to_case(Case::Pascal);
+        writeln!(s)?;
+        write_interface(&mut s, &format!("{}Request", name), &endpoint.parameters)?;
+        writeln!(s)?;
+        write_interface(&mut s, &format!("{}Response", name), &endpoint.returns)?;
+        if let Some(ty) = &endpoint.stream_response {
+            writeln!(s)?;
+            writeln!(
+                s,
+                "export type {}StreamHandler = StreamHandler<{}>;",
+                name,
+                typescript_type(ty)
+            )?;
+        }
+    }
+    writeln!(s)?;
+    writeln!(s, "export class EndpointClient extends BaseClient {{")?;
+    writeln!(s, "  static connect(url: string, protocol: string): Promise<EndpointClient> {{")?;
+    writeln!(s, "    return new Promise((resolve, reject) => {{")?;
+    writeln!(s, "      const socket = new WebSocket(url, protocol);")?;
+    writeln!(s, "      socket.addEventListener(\"open\", () => resolve(new EndpointClient(socket)));")?;
+    writeln!(s, "      socket.addEventListener(\"error\", () => reject(new Error(`Failed to connect to ${{url}}`)));")?;
+    writeln!(s, "    }});")?;
+    writeln!(s, "  }}")?;
+    for service in services {
+        writeln!(s)?;
+        writeln!(s, "  // Service {} ({})", service.name, service.id)?;
+        for endpoint in &service.endpoints {
+            write_client_method(&mut s, endpoint)?;
+        }
+    }
+    writeln!(s, "}}")?;
+    Ok(s)
+}
+
+/// Generates the TypeScript SDK of the given services and writes it to `path`.
+pub fn write_typescript_source(services: &[Service], path: impl AsRef<Path>) -> Result<()> {
+    let source = gen_typescript_source(services)?;
+    std::fs::write(path, source)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gen_typescript_source() {
+        let service = Service::new(
+            "user",
+            1,
+            vec![EndpointSchema::new(
+                "UserSubscribeOrders",
+                10030,
+                vec![
+                    Field::new("symbol", Type::optional(Type::String)),
+                    Field::new("amount", Type::BlockchainDecimal),
+                ],
+                vec![Field::new(
+                    "data",
+                    Type::datatable("UserOrder", vec![Field::new("created_at", Type::TimeStampMs)]),
+                )],
+            )
+            .with_stream_response_type(Type::struct_ref("UserOrder"))],
+        );
+        let source = gen_typescript_source(&[service]).unwrap();
+        assert!(source.contains("export interface UserOrder {\n  createdAt: number;\n}"));
+        assert!(source.contains("// values beyond Number.MAX_SAFE_INTEGER (2^53 - 1) lose precision.\n"));
+        assert!(source.contains("  symbol?: string | null;\n  amount: string;\n"));
+        assert!(source.contains("export interface UserSubscribeOrdersResponse {\n  data: UserOrder[];\n}"));
+        assert!(source.contains("export type UserSubscribeOrdersStreamHandler = StreamHandler<UserOrder>;"));
+        assert!(source.contains(
+            "  userSubscribeOrders(params: UserSubscribeOrdersRequest, onStream: UserSubscribeOrdersStreamHandler)"
+        ));
+        assert!(source.contains("    return this.request(10030, params, onStream);"));
+    }
+}
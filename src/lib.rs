@@ -1,2 +1,5 @@
+extern crate self as endpoint_libs;
+
+pub mod codegen;
 pub mod libs;
 pub mod model;
@@ -14,6 +14,7 @@ pub mod deserializer_wrapper;
 pub mod error_code;
 pub mod handler;
 pub mod listener;
+pub mod pagination;
 pub mod warn;
 
 pub const DEFAULT_LIMIT: i32 = 20;
@@ -1,21 +1,45 @@
 use deadpool_postgres::*;
 use eyre::*;
+use futures::stream::{self, BoxStream};
+use futures::{StreamExt, TryStreamExt};
 use postgres_from_row::FromRow;
 use secrecy::SecretString;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::future::Future;
 use std::path::Path;
 use std::process::Command;
 use std::time::Duration;
 pub use tokio_postgres::types::ToSql;
 pub use tokio_postgres::Row;
+mod cache;
+mod copy;
 mod data_thread;
+mod jsonb;
+mod migration;
+mod mock;
+mod notify;
+mod policy;
 mod pooled;
+mod replica;
+mod tls;
+mod transaction;
+pub use cache::*;
+pub use copy::*;
 pub use data_thread::*;
+pub use jsonb::*;
+pub use migration::*;
+pub use mock::*;
+pub use notify::*;
+pub use policy::*;
 pub use pooled::*;
+pub use replica::*;
+pub use tls::*;
+pub use transaction::*;
 
 use super::datatable::RDataTable;
+use crate::model::Field;
 
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct DatabaseConfig {
@@ -71,18 +95,46 @@ pub struct DatabaseConfig {
 
     /// [`Pool`] configuration.
     pub pool: Option<PoolConfig>,
+
+    /// TLS configuration, used unless `ssl_mode` is `Disable`. Required if `ssl_mode` is `Require`.
+    pub tls: Option<DatabaseTlsConfig>,
+
+    /// Default [`QueryPolicy`] of the requests executed by the client.
+    pub query_policy: Option<QueryPolicy>,
 }
 
 pub trait DatabaseRequest: Send {
     type ResponseRow: Send + Sync + Clone + Serialize + DeserializeOwned + FromRow;
     fn statement(&self) -> &str;
     fn params(&self) -> Vec<&(dyn ToSql + Sync)>;
+    /// Overrides the client's [`QueryPolicy`] for this request.
+    fn query_policy(&self) -> Option<QueryPolicy> {
+        None
+    }
+    /// Whether executing the request twice has the same effect as once. Only idempotent requests are
+    /// retried after a connection error, as the server may have applied them before it was lost.
+    fn idempotent(&self) -> bool {
+        false
+    }
+    /// Whether the request only reads, so that it can be routed to a replica.
+    fn read_only(&self) -> bool {
+        false
+    }
+    /// Caches the response when executed by a [`CachedDbClient`], not cached by default.
+    fn cache_policy(&self) -> Option<CachePolicy> {
+        None
+    }
 }
 pub type DatabaseRequestBoxed = Box<dyn DatabaseRequest<ResponseRow = Row>>;
 #[derive(Clone)]
 pub enum DbClient {
     Pooled(PooledDbClient),
     Threaded(ThreadedDbClient),
+    Replicated(ReplicatedDbClient),
+    /// Answers with registered responses, for tests, see [`MockDbClient`]
+    Mock(MockDbClient),
+    /// Serves requests with a [`CachePolicy`] from a cache, see [`CachedDbClient`]
+    Cached(CachedDbClient),
 }
 impl From<PooledDbClient> for DbClient {
     fn from(client: PooledDbClient) -> Self {
@@ -94,7 +146,27 @@ impl From<ThreadedDbClient> for DbClient {
         Self::Threaded(value)
     }
 }
+impl From<ReplicatedDbClient> for DbClient {
+    fn from(value: ReplicatedDbClient) -> Self {
+        Self::Replicated(value)
+    }
+}
+impl From<MockDbClient> for DbClient {
+    fn from(value: MockDbClient) -> Self {
+        Self::Mock(value)
+    }
+}
+impl From<CachedDbClient> for DbClient {
+    fn from(value: CachedDbClient) -> Self {
+        Self::Cached(value)
+    }
+}
 impl DbClient {
+    /// Wraps the client in a [`CachedDbClient`] with a new cache.
+    pub fn with_cache(self, config: QueryCacheConfig) -> Self {
+        Self::Cached(CachedDbClient::new(self, config))
+    }
+
     pub async fn execute<T>(&self, req: T) -> Result<RDataTable<T::ResponseRow>>
     where
         T: DatabaseRequest + Sync + Send + Debug + 'static,
@@ -103,6 +175,84 @@ impl DbClient {
         match self {
             DbClient::Pooled(client) => client.execute(req).await,
             DbClient::Threaded(client) => client.execute(req).await,
+            DbClient::Replicated(client) => client.execute(req).await,
+            DbClient::Mock(client) => client.execute(req).await,
+            DbClient::Cached(client) => client.execute(req).await,
+        }
+    }
+
+    /// Executes the request and yields its rows as they arrive, see [`PooledDbClient::execute_stream`].
+    pub fn execute_stream<T>(&self, req: T) -> BoxStream<'static, Result<T::ResponseRow>>
+    where
+        T: DatabaseRequest + Debug + 'static,
+    {
+        match self {
+            DbClient::Pooled(client) => client.execute_stream(req),
+            DbClient::Threaded(client) => client.execute_stream(req),
+            DbClient::Replicated(client) => client.execute_stream(req),
+            DbClient::Mock(client) => {
+                let client = client.clone();
+                stream::once(async move { client.execute(req).await })
+                    .map_ok(|rows| stream::iter(rows.into_iter().map(Ok)))
+                    .try_flatten()
+                    .boxed()
+            }
+            // streams are not cached
+            DbClient::Cached(client) => client.inner().execute_stream(req),
+        }
+    }
+
+    /// Bulk inserts `rows` into `columns` of `table` with a binary `COPY`, see [`PooledDbClient::copy_in`].
+    pub async fn copy_in<R: Serialize>(
+        &self,
+        table: &str,
+        columns: &[Field],
+        rows: impl IntoIterator<Item = R>,
+    ) -> Result<u64> {
+        match self {
+            DbClient::Pooled(client) => client.copy_in(table, columns, rows).await,
+            DbClient::Threaded(client) => client.copy_in(table, columns, rows).await,
+            DbClient::Replicated(client) => client.primary().copy_in(table, columns, rows).await,
+            DbClient::Mock(client) => client.copy_in(table, columns, rows).await,
+            DbClient::Cached(client) => Box::pin(client.inner().copy_in(table, columns, rows)).await,
+        }
+    }
+
+    /// Starts listening on `channels` with a dedicated connection, see [`DbListener`].
+    pub async fn listen(&self, channels: &[&str]) -> Result<DbListener> {
+        match self {
+            DbClient::Pooled(client) => Ok(client.listen(channels)),
+            DbClient::Threaded(client) => client.listen(channels).await,
+            DbClient::Replicated(client) => Ok(client.primary().listen(channels)),
+            DbClient::Mock(client) => Ok(client.listen(channels)),
+            DbClient::Cached(client) => Box::pin(client.inner().listen(channels)).await,
+        }
+    }
+
+    /// Runs `f` in a `READ COMMITTED` transaction. It is committed if `f` returns `Ok` and rolled
+    /// back if `f` returns `Err` or panics. Use [`DbTransaction::transaction`] for nested savepoints.
+    pub async fn transaction<R, F, Fut>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(DbTransaction) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<R>> + Send + 'static,
+    {
+        self.transaction_with_isolation(IsolationLevel::default(), f).await
+    }
+
+    /// Runs `f` in a transaction with the given isolation level, see [`DbClient::transaction`].
+    pub async fn transaction_with_isolation<R, F, Fut>(&self, isolation: IsolationLevel, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(DbTransaction) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<R>> + Send + 'static,
+    {
+        match self {
+            DbClient::Pooled(client) => client.transaction(isolation, f).await,
+            DbClient::Threaded(client) => client.transaction(isolation, f).await,
+            DbClient::Replicated(client) => client.primary().transaction(isolation, f).await,
+            DbClient::Mock(client) => client.transaction(isolation, f).await,
+            DbClient::Cached(client) => Box::pin(client.inner().transaction_with_isolation(isolation, f)).await,
         }
     }
 }
@@ -0,0 +1,346 @@
+use eyre::*;
+use parking_lot::Mutex;
+use postgres_from_row::FromRow;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::libs::datatable::RDataTable;
+
+use super::{DatabaseRequest, DbClient};
+
+/// How the response of a request is cached, see [`DatabaseRequest::cache_policy`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// How long the response is served from the cache
+    pub ttl: Duration,
+    /// Tags to invalidate the response with, see [`QueryCache::invalidate_tag`]
+    pub tags: Vec<String>,
+}
+
+impl CachePolicy {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, tags: vec![] }
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryCacheConfig {
+    /// The least recently used responses are evicted beyond this number of entries
+    pub max_entries: usize,
+}
+
+impl Default for QueryCacheConfig {
+    fn default() -> Self {
+        Self { max_entries: 10_000 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries evicted to respect `max_entries`
+    pub evictions: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    request_type: &'static str,
+    statement: String,
+    params: Vec<String>,
+}
+
+struct CacheEntry {
+    /// `RDataTable<T::ResponseRow>` of the request type of the key
+    value: Arc<dyn Any + Send + Sync>,
+    expires_at: Instant,
+    tags: Vec<String>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Keys by last use, oldest first
+    lru: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    /// Bumped by every invalidation of the tag, so that responses queried before it aren't cached
+    tag_generations: HashMap<String, u64>,
+    /// Bumped by `invalidate_all`
+    generation: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, key: &CacheKey) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.lru.remove(&entry.last_used);
+            entry.last_used = tick;
+            self.lru.insert(tick, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.last_used);
+        }
+    }
+
+    /// Changes whenever a response with `tags` is invalidated, as generations only grow.
+    fn generation(&self, tags: &[String]) -> u64 {
+        tags.iter()
+            .filter_map(|x| self.tag_generations.get(x))
+            .fold(self.generation, |sum, x| sum.wrapping_add(*x))
+    }
+}
+
+/// `QueryCache` is an in-memory LRU cache of request responses, keyed by request type, statement
+/// and the `Debug` representation of the parameters.
+pub struct QueryCache {
+    config: QueryCacheConfig,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl QueryCache {
+    pub fn new(config: QueryCacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(CacheState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    fn key<T: DatabaseRequest>(req: &T) -> CacheKey {
+        CacheKey {
+            request_type: std::any::type_name::<T>(),
+            statement: req.statement().to_owned(),
+            params: req.params().iter().map(|x| format!("{:?}", x)).collect(),
+        }
+    }
+
+    fn get<R: Clone + 'static>(&self, key: &CacheKey) -> Option<RDataTable<R>> {
+        let mut state = self.state.lock();
+        let value = match state.entries.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => entry.value.clone(),
+            Some(_) => {
+                state.remove(key);
+                return None;
+            }
+            None => return None,
+        };
+        state.touch(key);
+        value.downcast_ref::<RDataTable<R>>().cloned()
+    }
+
+    fn generation(&self, policy: &CachePolicy) -> u64 {
+        self.state.lock().generation(&policy.tags)
+    }
+
+    /// Caches the response unless it was invalidated since `generation`, taken before the query.
+    fn insert<R: Send + Sync + 'static>(
+        &self,
+        key: CacheKey,
+        policy: CachePolicy,
+        generation: u64,
+        value: RDataTable<R>,
+    ) {
+        let mut state = self.state.lock();
+        if state.generation(&policy.tags) != generation {
+            return;
+        }
+        state.remove(&key);
+        state.tick += 1;
+        let tick = state.tick;
+        state.lru.insert(tick, key.clone());
+        state.entries.insert(
+            key,
+            CacheEntry {
+                value: Arc::new(value),
+                expires_at: Instant::now() + policy.ttl,
+                tags: policy.tags,
+                last_used: tick,
+            },
+        );
+        while state.entries.len() > self.config.max_entries {
+            let Some((_, oldest)) = state.lru.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Removes the responses cached with `tag`, including those of the queries in flight.
+    pub fn invalidate_tag(&self, tag: &str) {
+        let mut state = self.state.lock();
+        *state.tag_generations.entry(tag.to_owned()).or_default() += 1;
+        let keys: Vec<CacheKey> = state
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.tags.iter().any(|x| x == tag))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            state.remove(&key);
+        }
+    }
+
+    /// Removes every cached response, including those of the queries in flight.
+    pub fn invalidate_all(&self) {
+        let mut state = self.state.lock();
+        state.generation += 1;
+        state.entries.clear();
+        state.lru.clear();
+    }
+
+    pub fn stats(&self) -> QueryCacheStats {
+        QueryCacheStats {
+            entries: self.state.lock().entries.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// `CachedDbClient` serves requests that have a [`CachePolicy`] from a [`QueryCache`], and
+/// forwards everything else to the wrapped client.
+#[derive(Clone)]
+pub struct CachedDbClient {
+    inner: Box<DbClient>,
+    cache: Arc<QueryCache>,
+}
+
+impl CachedDbClient {
+    pub fn new(inner: DbClient, config: QueryCacheConfig) -> Self {
+        Self {
+            inner: Box::new(inner),
+            cache: Arc::new(QueryCache::new(config)),
+        }
+    }
+
+    pub fn inner(&self) -> &DbClient {
+        &self.inner
+    }
+
+    pub fn cache(&self) -> &Arc<QueryCache> {
+        &self.cache
+    }
+
+    pub async fn execute<T>(&self, req: T) -> Result<RDataTable<T::ResponseRow>>
+    where
+        T: DatabaseRequest + Sync + Send + Debug + 'static,
+        T::ResponseRow: FromRow + Sync + Send + Clone + Debug + Sized + 'static,
+    {
+        let Some(policy) = req.cache_policy() else {
+            return Box::pin(self.inner.execute(req)).await;
+        };
+        let key = QueryCache::key(&req);
+        if let Some(rows) = self.cache.get(&key) {
+            self.cache.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(rows);
+        }
+        self.cache.misses.fetch_add(1, Ordering::Relaxed);
+        let generation = self.cache.generation(&policy);
+        let rows = Box::pin(self.inner.execute(req)).await?;
+        self.cache.insert(key, policy, generation, rows.clone());
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::database::{MockDbClient, ToSql};
+    use std::result::Result::Ok;
+
+    #[derive(Debug)]
+    struct PriceReq {
+        symbol: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, postgres_from_row::FromRow)]
+    struct PriceRow {
+        price: f64,
+    }
+
+    impl DatabaseRequest for PriceReq {
+        type ResponseRow = PriceRow;
+        fn statement(&self) -> &str {
+            "SELECT price FROM tbl.price WHERE symbol = $1"
+        }
+        fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+            vec![&self.symbol]
+        }
+        fn cache_policy(&self) -> Option<CachePolicy> {
+            Some(CachePolicy::new(Duration::from_secs(60)).with_tag(format!("price:{}", self.symbol)))
+        }
+    }
+
+    fn price(symbol: &str) -> PriceReq {
+        PriceReq {
+            symbol: symbol.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_db_client() {
+        let mock = MockDbClient::new();
+        mock.on::<PriceReq>(vec![PriceRow { price: 1.0 }]);
+        let client = CachedDbClient::new(mock.clone().into(), QueryCacheConfig { max_entries: 2 });
+
+        client.execute(price("BTC")).await.unwrap();
+        client.execute(price("BTC")).await.unwrap();
+        assert_eq!(mock.calls().len(), 1);
+
+        client.cache().invalidate_tag("price:BTC");
+        client.execute(price("BTC")).await.unwrap();
+        assert_eq!(mock.calls().len(), 2);
+
+        client.execute(price("ETH")).await.unwrap();
+        client.execute(price("SOL")).await.unwrap();
+        let stats = client.cache().stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 4);
+        // BTC was the least recently used
+        client.execute(price("BTC")).await.unwrap();
+        assert_eq!(mock.calls().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_during_query() {
+        let mock = MockDbClient::new();
+        let client = CachedDbClient::new(mock.clone().into(), QueryCacheConfig::default());
+        let cache = client.cache().clone();
+        mock.on_with::<PriceReq>(move |req| {
+            // the price changes while it is queried
+            cache.invalidate_tag(&format!("price:{}", req.symbol));
+            Ok(vec![PriceRow { price: 1.0 }])
+        });
+
+        client.execute(price("BTC")).await.unwrap();
+        client.execute(price("BTC")).await.unwrap();
+        assert_eq!(mock.calls().len(), 2);
+        assert_eq!(client.cache().stats().entries, 0);
+    }
+}
@@ -0,0 +1,274 @@
+use bytes::{BufMut, BytesMut};
+use chrono::NaiveDate;
+use convert_case::{Case, Casing};
+use eyre::*;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error as StdError;
+use std::net::IpAddr;
+use std::pin::pin;
+use std::result::Result::Ok;
+use std::str::FromStr;
+use tokio_postgres::binary_copy::BinaryCopyInWriter;
+use tokio_postgres::types::{to_sql_checked, IsNull, Kind, ToSql, Type as PgType};
+use tracing::*;
+
+use crate::model::{Field, Type};
+
+use super::PooledDbClient;
+
+/// A value of a `COPY` row, converted from JSON according to the `model::Type` of its column.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CopyValue {
+    Null,
+    Int(i32),
+    BigInt(i64),
+    Numeric(f64),
+    Boolean(bool),
+    String(String),
+    Bytea(Vec<u8>),
+    UUID([u8; 16]),
+    Inet(IpAddr),
+    Date(NaiveDate),
+    Decimal(Decimal),
+    Json(Value),
+    /// The label of a Postgres enum
+    Enum(String),
+    Array(Vec<CopyValue>),
+}
+
+impl CopyValue {
+    /// Converts a JSON value to the representation of `ty` used by the generated Postgres schema.
+    pub fn from_json(ty: &Type, value: &Value) -> Result<Self> {
+        let unexpected = || eyre!("expected {:?}, got {}", ty, value);
+        Ok(match ty {
+            Type::Optional(_) if value.is_null() => CopyValue::Null,
+            Type::Optional(ty) => Self::from_json(ty, value)?,
+            _ if value.is_null() => bail!("expected {:?}, got null", ty),
+            Type::Int => CopyValue::Int(
+                value
+                    .as_i64()
+                    .and_then(|x| i32::try_from(x).ok())
+                    .ok_or_else(unexpected)?,
+            ),
+            Type::BigInt | Type::TimeStampMs => CopyValue::BigInt(value.as_i64().ok_or_else(unexpected)?),
+            Type::Numeric => CopyValue::Numeric(value.as_f64().ok_or_else(unexpected)?),
+            Type::Boolean => CopyValue::Boolean(value.as_bool().ok_or_else(unexpected)?),
+            Type::String | Type::BlockchainAddress | Type::BlockchainTransactionHash => {
+                CopyValue::String(value.as_str().ok_or_else(unexpected)?.to_owned())
+            }
+            Type::Bytea => CopyValue::Bytea(serde_json::from_value(value.clone())?),
+            Type::UUID => {
+                let hex = value.as_str().ok_or_else(unexpected)?.replace('-', "");
+                let bytes = hex::decode(hex)?;
+                CopyValue::UUID(bytes.try_into().map_err(|_| unexpected())?)
+            }
+            Type::Inet => CopyValue::Inet(value.as_str().ok_or_else(unexpected)?.parse()?),
+            Type::Date => CopyValue::Date(NaiveDate::parse_from_str(
+                value.as_str().ok_or_else(unexpected)?,
+                "%Y-%m-%d",
+            )?),
+            Type::BlockchainDecimal => CopyValue::Decimal(match value {
+                Value::String(x) => Decimal::from_str(x)?,
+                Value::Number(x) => Decimal::from_str(&x.to_string())?,
+                _ => bail!(unexpected()),
+            }),
+            Type::Struct { .. }
+            | Type::StructRef(_)
+            | Type::Object
+            | Type::DataTable { .. }
+            | Type::DataTableIdentifer { .. } => CopyValue::Json(value.clone()),
+            Type::Enum { .. } | Type::EnumRef(_) => {
+                CopyValue::Enum(value.as_str().ok_or_else(unexpected)?.to_case(Case::Snake))
+            }
+            Type::Vec(ty) => CopyValue::Array(
+                value
+                    .as_array()
+                    .ok_or_else(unexpected)?
+                    .iter()
+                    .map(|x| Self::from_json(ty, x))
+                    .collect::<Result<_>>()?,
+            ),
+            Type::Unit => bail!("unit columns can't be copied"),
+        })
+    }
+}
+
+impl ToSql for CopyValue {
+    fn to_sql(&self, ty: &PgType, out: &mut BytesMut) -> Result<IsNull, Box<dyn StdError + Sync + Send>> {
+        match self {
+            CopyValue::Null => Ok(IsNull::Yes),
+            CopyValue::Int(x) => x.to_sql_checked(ty, out),
+            CopyValue::BigInt(x) => x.to_sql_checked(ty, out),
+            CopyValue::Numeric(x) => x.to_sql_checked(ty, out),
+            CopyValue::Boolean(x) => x.to_sql_checked(ty, out),
+            CopyValue::String(x) => x.to_sql_checked(ty, out),
+            CopyValue::Bytea(x) => x.to_sql_checked(ty, out),
+            CopyValue::UUID(x) if *ty == PgType::UUID => {
+                out.put_slice(x);
+                Ok(IsNull::No)
+            }
+            CopyValue::Inet(x) => x.to_sql_checked(ty, out),
+            CopyValue::Date(x) => x.to_sql_checked(ty, out),
+            CopyValue::Decimal(x) => x.to_sql_checked(ty, out),
+            CopyValue::Json(x) => x.to_sql_checked(ty, out),
+            CopyValue::Enum(x) if matches!(ty.kind(), Kind::Enum(_)) => {
+                out.put_slice(x.as_bytes());
+                Ok(IsNull::No)
+            }
+            CopyValue::Array(x) => x.to_sql_checked(ty, out),
+            _ => Err(format!("cannot copy {:?} into a column of type {}", self, ty).into()),
+        }
+    }
+
+    fn accepts(_ty: &PgType) -> bool {
+        // checked per variant in to_sql
+        true
+    }
+
+    to_sql_checked!();
+}
+
+pub(super) fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Converts `row` to the values of `columns`. Columns are looked up by name, then by camelCase
+/// name to match the JSON convention of endpoint types.
+pub fn copy_row(columns: &[Field], row: &impl Serialize) -> Result<Vec<CopyValue>> {
+    let row = serde_json::to_value(row)?;
+    let object = row.as_object().context("COPY rows must serialize to objects")?;
+    columns
+        .iter()
+        .map(|column| {
+            let value = object
+                .get(&column.name)
+                .or_else(|| object.get(&column.name.to_case(Case::Camel)))
+                .unwrap_or(&Value::Null);
+            CopyValue::from_json(&column.ty, value).with_context(|| format!("column {}", column.name))
+        })
+        .collect()
+}
+
+impl PooledDbClient {
+    /// Bulk inserts `rows` into `columns` of `table` (optionally schema qualified, e.g. `tbl.trade`)
+    /// with a binary `COPY`. Returns the number of rows inserted.
+    ///
+    /// Rows are serialized to JSON and converted according to the `model::Type` of each column, see
+    /// [`CopyValue::from_json`]. Nothing is inserted if any row fails.
+    pub async fn copy_in<R: Serialize>(
+        &self,
+        table: &str,
+        columns: &[Field],
+        rows: impl IntoIterator<Item = R>,
+    ) -> Result<u64> {
+        self.copy_in_values(table, columns, rows.into_iter().map(|row| copy_row(columns, &row)))
+            .await
+    }
+
+    pub(super) async fn copy_in_values(
+        &self,
+        table: &str,
+        columns: &[Field],
+        rows: impl Iterator<Item = Result<Vec<CopyValue>>>,
+    ) -> Result<u64> {
+        let begin = std::time::Instant::now();
+        let table = table.split('.').map(quote_ident).collect::<Vec<_>>().join(".");
+        let names = columns.iter().map(|x| quote_ident(&x.name)).collect::<Vec<_>>().join(", ");
+        let client = self.pool.get().await.context("Failed to connect to database")?;
+        // the column types come from the table itself, so that enum types are resolved by the server
+        let types: Vec<PgType> = client
+            .prepare(&format!("SELECT {} FROM {} LIMIT 0", names, table))
+            .await?
+            .columns()
+            .iter()
+            .map(|x| x.type_().clone())
+            .collect();
+        let sink = client
+            .copy_in(&format!("COPY {} ({}) FROM STDIN BINARY", table, names))
+            .await?;
+        let mut writer = pin!(BinaryCopyInWriter::new(sink, &types));
+        for row in rows {
+            let row = row?;
+            let values: Vec<&(dyn ToSql + Sync)> = row.iter().map(|x| x as _).collect();
+            writer.as_mut().write(&values).await?;
+        }
+        let count = writer.finish().await?;
+        debug!("Copied {} rows into {} in {:?}", count, table, begin.elapsed());
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::libs::database::{connect_to_database, database_test_config, DbClient};
+    use crate::model::EnumVariant;
+
+    #[test]
+    fn test_copy_row() {
+        let columns = vec![
+            Field::new("trade_id", Type::BigInt),
+            Field::new("side", Type::enum_ref("side")),
+            Field::new("price", Type::BlockchainDecimal),
+            Field::new("note", Type::optional(Type::String)),
+        ];
+        let row = json!({"tradeId": 1, "side": "Buy", "price": "1.5"});
+        assert_eq!(
+            copy_row(&columns, &row).unwrap(),
+            vec![
+                CopyValue::BigInt(1),
+                CopyValue::Enum("buy".to_owned()),
+                CopyValue::Decimal(Decimal::from_str("1.5").unwrap()),
+                CopyValue::Null,
+            ]
+        );
+        assert!(copy_row(&columns, &json!({"side": "Buy", "price": "1"})).is_err());
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Trade {
+        trade_id: i64,
+        side: String,
+        price: Decimal,
+        tags: Vec<String>,
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local postgres"]
+    async fn test_copy_in() {
+        let client: DbClient = connect_to_database(database_test_config()).await.unwrap().into();
+        let side = Type::enum_("side", vec![EnumVariant::new("buy", 1), EnumVariant::new("sell", 2)]);
+        client
+            .transaction(|tx| async move {
+                tx.batch_execute(
+                    "DROP TABLE IF EXISTS copy_test;
+                    DROP TYPE IF EXISTS enum_copy_side;
+                    CREATE TYPE enum_copy_side AS ENUM ('buy', 'sell');
+                    CREATE TABLE copy_test (
+                        trade_id bigint, side enum_copy_side, price decimal(56, 18), tags varchar[]
+                    );",
+                )
+                .await
+            })
+            .await
+            .unwrap();
+        let columns = vec![
+            Field::new("trade_id", Type::BigInt),
+            Field::new("side", side),
+            Field::new("price", Type::BlockchainDecimal),
+            Field::new("tags", Type::vec(Type::String)),
+        ];
+        let rows = (0..1000).map(|i| Trade {
+            trade_id: i,
+            side: if i % 2 == 0 { "Buy" } else { "Sell" }.to_owned(),
+            price: Decimal::new(i, 2),
+            tags: vec!["a".to_owned()],
+        });
+        assert_eq!(client.copy_in("public.copy_test", &columns, rows).await.unwrap(), 1000);
+    }
+}
@@ -1,68 +1,295 @@
 use eyre::*;
 use futures::future::BoxFuture;
-use futures::FutureExt;
+use futures::stream::{self, BoxStream};
+use futures::{FutureExt, SinkExt, StreamExt, TryStreamExt};
 use postgres_from_row::FromRow;
-use std::any::Any;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::result::Result::Ok;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use tracing::*;
 
 use crate::libs::datatable::RDataTable;
+use crate::libs::signal::CANCELLATION_TOKEN;
+use crate::model::Field;
 
-use super::{DatabaseRequest, PooledDbClient};
+use super::{copy_row, DatabaseRequest, DbListener, DbTransaction, IsolationLevel, PooledDbClient};
 
-type DbExecutionRequestType =
-    Box<dyn FnOnce(&PooledDbClient) -> BoxFuture<Box<dyn Any + Sync + Send>> + Send>;
+/// A unit of work run by a database worker. It sends its own result back to the caller.
+type DbJob = Box<dyn FnOnce(PooledDbClient) -> BoxFuture<'static, ()> + Send>;
 
-struct DbExecutionQuery {
-    request: DbExecutionRequestType,
-    result: tokio::sync::oneshot::Sender<Box<dyn Any + Sync + Send>>,
+/// How many rows of a streamed request are buffered between the worker and the consumer.
+const STREAM_BUFFER: usize = 256;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThreadedDbClientConfig {
+    /// Number of worker threads, each with its own runtime
+    pub workers: usize,
+    /// Number of requests that can be queued before callers wait for room
+    pub queue_size: usize,
 }
+
+impl Default for ThreadedDbClientConfig {
+    fn default() -> Self {
+        Self {
+            workers: 1,
+            queue_size: 100,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThreadedDbMetrics {
+    /// Requests waiting for a worker
+    pub queue_depth: usize,
+    pub queue_capacity: usize,
+    /// Workers currently running a request
+    pub busy_workers: usize,
+    /// Requests completed since the client was spawned
+    pub completed: u64,
+}
+
+#[derive(Default)]
+struct WorkerCounters {
+    busy: AtomicUsize,
+    completed: AtomicU64,
+}
+
+/// `ThreadedDbClient` runs requests on dedicated worker threads, so that slow queries don't
+/// compete with the caller's runtime. Clones share the same workers.
 #[derive(Clone)]
 pub struct ThreadedDbClient {
-    tx: tokio::sync::mpsc::Sender<DbExecutionQuery>,
+    tx: kanal::AsyncSender<DbJob>,
+    counters: Arc<WorkerCounters>,
 }
+
+/// Joins the worker threads of a `ThreadedDbClient`. They exit once `CANCELLATION_TOKEN` is
+/// cancelled and the queued requests are drained, or once every client is dropped.
+pub struct ThreadedDbJoinHandle {
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ThreadedDbJoinHandle {
+    /// Blocks until all workers have exited.
+    pub fn join(self) -> Result<()> {
+        for thread in self.threads {
+            thread.join().map_err(|_| eyre!("Database worker panicked"))?;
+        }
+        Ok(())
+    }
+
+    /// Waits until all workers have exited, without blocking the runtime.
+    pub async fn wait(self) -> Result<()> {
+        tokio::task::spawn_blocking(move || self.join()).await?
+    }
+}
+
 impl ThreadedDbClient {
-    pub async fn execute<T>(&self, req: T) -> Result<RDataTable<T::ResponseRow>>
+    /// Runs `f` with the pooled client on a worker and returns its result. Waits for room if the
+    /// queue is full.
+    async fn run<R, F>(&self, f: F) -> Result<R>
     where
-        T: DatabaseRequest + Sync + Send + Debug + 'static,
-        T::ResponseRow: FromRow + Sync + Send + Clone + Debug + Sized + 'static,
+        R: Send + 'static,
+        F: FnOnce(PooledDbClient) -> BoxFuture<'static, R> + Send + 'static,
     {
-        let request: DbExecutionRequestType = Box::new(move |client: &PooledDbClient| {
+        ensure!(!CANCELLATION_TOKEN.is_cancelled(), "Database workers are shutting down");
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let job: DbJob = Box::new(move |client| {
             async move {
-                let result = client.execute(req).await;
-                Box::new(result) as _
+                let _ = tx.send(f(client).await);
             }
             .boxed()
         });
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        let query = DbExecutionQuery {
-            request,
-            result: tx,
-        };
         self.tx
-            .send(query)
+            .send(job)
             .await
-            .map_err(|_| eyre!("send failed"))?;
-        let result = rx.await?;
-        let result = result
-            .downcast::<Result<RDataTable<T::ResponseRow>>>()
-            .expect("downcast failed");
-        *result
+            .map_err(|_| eyre!("Database workers have stopped"))?;
+        rx.await.map_err(|_| eyre!("Database worker dropped the request"))
     }
-}
-pub fn spawn_thread_db_client(pooled: PooledDbClient) -> Result<ThreadedDbClient> {
-    let (tx, mut rx) = tokio::sync::mpsc::channel(100);
-    let client = ThreadedDbClient { tx };
-    std::thread::spawn(move || {
-        tokio::runtime::Runtime::new()
-            .unwrap()
-            .block_on(async move {
-                let client = pooled;
-                while let Some(x) = rx.recv().await {
-                    let DbExecutionQuery { request, result } = x;
-                    let result1 = request(&client).await;
-                    let _ = result.send(result1);
+
+    pub fn metrics(&self) -> ThreadedDbMetrics {
+        ThreadedDbMetrics {
+            queue_depth: self.tx.len(),
+            queue_capacity: self.tx.capacity(),
+            busy_workers: self.counters.busy.load(Ordering::Relaxed),
+            completed: self.counters.completed.load(Ordering::Relaxed),
+        }
+    }
+
+    pub async fn execute<T>(&self, req: T) -> Result<RDataTable<T::ResponseRow>>
+    where
+        T: DatabaseRequest + Sync + Send + Debug + 'static,
+        T::ResponseRow: FromRow + Sync + Send + Clone + Debug + Sized + 'static,
+    {
+        self.run(move |client| async move { client.execute(req).await }.boxed())
+            .await?
+    }
+
+    /// Streams the rows of the request, see [`PooledDbClient::execute_stream`]. The query runs on a
+    /// worker and the rows are forwarded through a bounded channel.
+    pub fn execute_stream<T>(&self, req: T) -> BoxStream<'static, Result<T::ResponseRow>>
+    where
+        T: DatabaseRequest + Debug + 'static,
+    {
+        let this = self.clone();
+        let (tx, rx) = futures::channel::mpsc::channel(STREAM_BUFFER);
+        stream::once(async move {
+            this.run(move |client| {
+                async move {
+                    // spawned so that the worker keeps serving other requests while streaming
+                    tokio::spawn(async move {
+                        let mut tx = tx;
+                        let mut rows = client.execute_stream(req);
+                        while let Some(row) = rows.next().await {
+                            if tx.send(row).await.is_err() {
+                                break;
+                            }
+                        }
+                    });
                 }
+                .boxed()
             })
-    });
+            .await?;
+            Ok::<_, Error>(rx)
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Bulk inserts rows with a binary `COPY`, see [`PooledDbClient::copy_in`]. The rows are converted
+    /// before being sent to a worker.
+    pub async fn copy_in<R: Serialize>(
+        &self,
+        table: &str,
+        columns: &[Field],
+        rows: impl IntoIterator<Item = R>,
+    ) -> Result<u64> {
+        let rows = rows
+            .into_iter()
+            .map(|row| copy_row(columns, &row))
+            .collect::<Result<Vec<_>>>()?;
+        let table = table.to_owned();
+        let columns = columns.to_vec();
+        self.run(move |client| {
+            async move { client.copy_in_values(&table, &columns, rows.into_iter().map(Ok)).await }.boxed()
+        })
+        .await?
+    }
+
+    /// Starts listening on `channels`, see [`PooledDbClient::listen`]. The listener runs on a
+    /// worker.
+    pub async fn listen(&self, channels: &[&str]) -> Result<DbListener> {
+        let channels: Vec<String> = channels.iter().map(|x| x.to_string()).collect();
+        self.run(move |client| {
+            async move {
+                let channels: Vec<&str> = channels.iter().map(|x| x.as_str()).collect();
+                client.listen(&channels)
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    /// Runs `f` in a transaction on a worker, see [`PooledDbClient::transaction`].
+    pub async fn transaction<R, F, Fut>(&self, isolation: IsolationLevel, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(DbTransaction) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<R>> + Send + 'static,
+    {
+        self.run(move |client| async move { client.transaction(isolation, f).await }.boxed())
+            .await?
+    }
+}
+
+async fn run_job(client: &PooledDbClient, counters: &WorkerCounters, job: DbJob) {
+    counters.busy.fetch_add(1, Ordering::Relaxed);
+    // a panicking request drops its result sender, which the caller sees as an error
+    if AssertUnwindSafe(job(client.clone())).catch_unwind().await.is_err() {
+        error!("Database request panicked");
+    }
+    counters.busy.fetch_sub(1, Ordering::Relaxed);
+    counters.completed.fetch_add(1, Ordering::Relaxed);
+}
+
+async fn run_worker(client: PooledDbClient, rx: kanal::AsyncReceiver<DbJob>, counters: Arc<WorkerCounters>) {
+    loop {
+        tokio::select! {
+            biased;
+            job = rx.recv() => match job {
+                Ok(job) => run_job(&client, &counters, job).await,
+                // every client was dropped
+                Err(_) => return,
+            },
+            _ = CANCELLATION_TOKEN.cancelled() => {
+                while let Ok(Some(job)) = rx.try_recv() {
+                    run_job(&client, &counters, job).await;
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Spawns the worker threads of a `ThreadedDbClient`.
+pub fn spawn_threaded_db_client(
+    pooled: PooledDbClient,
+    config: ThreadedDbClientConfig,
+) -> Result<(ThreadedDbClient, ThreadedDbJoinHandle)> {
+    ensure!(config.workers > 0, "ThreadedDbClient needs at least one worker");
+    let (tx, rx) = kanal::bounded_async(config.queue_size);
+    let counters = Arc::new(WorkerCounters::default());
+    let mut threads = vec![];
+    for i in 0..config.workers {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let client = pooled.clone();
+        let rx = rx.clone();
+        let counters = counters.clone();
+        let thread = std::thread::Builder::new()
+            .name(format!("db-worker-{}", i))
+            .spawn(move || runtime.block_on(run_worker(client, rx, counters)))?;
+        threads.push(thread);
+    }
+    Ok((ThreadedDbClient { tx, counters }, ThreadedDbJoinHandle { threads }))
+}
+
+/// Spawns a `ThreadedDbClient` with the default configuration, detaching its worker.
+pub fn spawn_thread_db_client(pooled: PooledDbClient) -> Result<ThreadedDbClient> {
+    let (client, _) = spawn_threaded_db_client(pooled, ThreadedDbClientConfig::default())?;
     Ok(client)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::database::{connect_to_database, database_test_config};
+
+    #[tokio::test]
+    async fn test_threaded_workers() {
+        // the pool connects lazily, so no database is needed for jobs that don't query
+        let pooled = connect_to_database(database_test_config()).await.unwrap();
+        let config = ThreadedDbClientConfig {
+            workers: 2,
+            queue_size: 4,
+        };
+        let (client, handle) = spawn_threaded_db_client(pooled, config).unwrap();
+        let value = client.run(|_| async { 42 }.boxed()).await.unwrap();
+        assert_eq!(value, 42);
+        let result: Result<()> = client.run(|_| async { panic!("request failed") }.boxed()).await;
+        assert!(result.is_err());
+        // the worker survived the panic
+        assert_eq!(client.run(|_| async { "ok" }.boxed()).await.unwrap(), "ok");
+        assert_eq!(client.metrics().queue_capacity, 4);
+
+        // the workers exit once every client is dropped
+        let counters = client.counters.clone();
+        drop(client);
+        handle.wait().await.unwrap();
+        assert_eq!(counters.completed.load(Ordering::Relaxed), 3);
+    }
+}
@@ -0,0 +1,29 @@
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use tokio_postgres::types::{to_sql_checked, FromSql, IsNull, Json, ToSql, Type};
+
+/// `Jsonb` maps a `json`/`jsonb` column to `T`, like `tokio_postgres::types::Json`, but
+/// (de)serializes as `T` itself so that rows and requests holding it keep their serde derives.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Jsonb<T>(pub T);
+
+impl<T: Serialize + Debug> ToSql for Jsonb<T> {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        Json(&self.0).to_sql(ty, out)
+    }
+    fn accepts(ty: &Type) -> bool {
+        <Json<T> as ToSql>::accepts(ty)
+    }
+    to_sql_checked!();
+}
+
+impl<'a, T: Deserialize<'a>> FromSql<'a> for Jsonb<T> {
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Json::<T>::from_sql(ty, raw).map(|x| Jsonb(x.0))
+    }
+    fn accepts(ty: &Type) -> bool {
+        <Json<T> as FromSql>::accepts(ty)
+    }
+}
@@ -0,0 +1,336 @@
+use alloy::primitives::keccak256;
+use deadpool_postgres::Object;
+use eyre::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::*;
+
+use crate::libs::utils::get_time_milliseconds;
+
+use super::PooledDbClient;
+
+/// The table keeping track of the applied migrations.
+pub const MIGRATIONS_TABLE: &str = "_migrations";
+
+/// The advisory lock key taken by migration runners, so that only one applies migrations at a time.
+const MIGRATION_LOCK_KEY: i64 = 0x5f6d_6967_7261_7465;
+
+/// `Migration` is a versioned SQL script loaded from a migrations directory.
+///
+/// Files are named `<version>_<name>.sql`, with an optional `<version>_<name>.down.sql` that
+/// reverts it (e.g. `0001_create_user.sql` and `0001_create_user.down.sql`).
+#[derive(Clone, Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: String,
+    pub up: String,
+    pub down: Option<String>,
+    /// Hex encoded keccak256 of `up`
+    pub checksum: String,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MigrationState {
+    /// The migration file exists but has not been applied
+    Pending,
+    /// The migration has been applied and the file is unchanged
+    Applied,
+    /// The migration has been applied but the file has changed since
+    ChecksumMismatch,
+    /// The migration has been applied but its file no longer exists
+    Missing,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub state: MigrationState,
+    /// When the migration was applied, in milliseconds
+    pub applied_at: Option<i64>,
+}
+
+struct AppliedMigration {
+    name: String,
+    checksum: String,
+    applied_at: i64,
+}
+
+fn checksum(sql: &str) -> String {
+    hex::encode(keccak256(sql.as_bytes()))
+}
+
+/// Loads the migrations of `dir`, sorted by version.
+pub fn load_migrations(dir: impl AsRef<Path>) -> Result<Vec<Migration>> {
+    let dir = dir.as_ref();
+    let mut ups = BTreeMap::new();
+    let mut downs = BTreeMap::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|x| x.to_str()) else {
+            continue;
+        };
+        let Some(stem) = file_name.strip_suffix(".sql") else {
+            continue;
+        };
+        let (stem, is_down) = match stem.strip_suffix(".down") {
+            Some(stem) => (stem, true),
+            None => (stem, false),
+        };
+        let (version, name) = stem
+            .split_once('_')
+            .with_context(|| format!("Migration file {} should be named <version>_<name>.sql", file_name))?;
+        let version: i64 = version
+            .parse()
+            .with_context(|| format!("Invalid migration version in {}", file_name))?;
+        let sql = std::fs::read_to_string(&path)?;
+        let target = if is_down { &mut downs } else { &mut ups };
+        if let Some((other, _)) = target.insert(version, (name.to_string(), sql)) {
+            bail!("Duplicate migration version {}: {} and {}", version, other, name);
+        }
+    }
+    for version in downs.keys() {
+        ensure!(ups.contains_key(version), "Down migration {} has no up migration", version);
+    }
+    Ok(ups
+        .into_iter()
+        .map(|(version, (name, up))| Migration {
+            version,
+            checksum: checksum(&up),
+            down: downs.remove(&version).map(|(_, sql)| sql),
+            name,
+            up,
+        })
+        .collect())
+}
+
+impl PooledDbClient {
+    async fn ensure_migrations_table(&self, client: &mut Object) -> Result<()> {
+        let tx = client.transaction().await?;
+        tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK_KEY])
+            .await?;
+        tx.batch_execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                version bigint PRIMARY KEY,
+                name varchar NOT NULL,
+                checksum varchar NOT NULL,
+                applied_at bigint NOT NULL,
+                execution_ms bigint NOT NULL
+            )",
+            MIGRATIONS_TABLE
+        ))
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn applied_migrations(&self, client: &Object) -> Result<BTreeMap<i64, AppliedMigration>> {
+        let rows = client
+            .query(
+                &format!("SELECT version, name, checksum, applied_at FROM {}", MIGRATIONS_TABLE),
+                &[],
+            )
+            .await?;
+        let mut applied = BTreeMap::new();
+        for row in rows {
+            applied.insert(
+                row.try_get("version")?,
+                AppliedMigration {
+                    name: row.try_get("name")?,
+                    checksum: row.try_get("checksum")?,
+                    applied_at: row.try_get("applied_at")?,
+                },
+            );
+        }
+        Ok(applied)
+    }
+
+    /// Runs `up` (or `down`) of the migration in a transaction holding the migration lock.
+    /// Returns false if another runner already did it.
+    async fn run_migration(&self, client: &mut Object, migration: &Migration, up: bool) -> Result<bool> {
+        let tx = client.transaction().await?;
+        tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK_KEY])
+            .await?;
+        let applied = !tx
+            .query(
+                &format!("SELECT 1 FROM {} WHERE version = $1", MIGRATIONS_TABLE),
+                &[&migration.version],
+            )
+            .await?
+            .is_empty();
+        if applied == up {
+            return Ok(false);
+        }
+        let begin = std::time::Instant::now();
+        if up {
+            info!("Applying migration {} {}", migration.version, migration.name);
+            tx.batch_execute(&migration.up)
+                .await
+                .with_context(|| format!("Failed to apply migration {} {}", migration.version, migration.name))?;
+            tx.execute(
+                &format!(
+                    "INSERT INTO {} (version, name, checksum, applied_at, execution_ms) VALUES ($1, $2, $3, $4, $5)",
+                    MIGRATIONS_TABLE
+                ),
+                &[
+                    &migration.version,
+                    &migration.name,
+                    &migration.checksum,
+                    &get_time_milliseconds(),
+                    &(begin.elapsed().as_millis() as i64),
+                ],
+            )
+            .await?;
+        } else {
+            info!("Reverting migration {} {}", migration.version, migration.name);
+            let down = migration
+                .down
+                .as_ref()
+                .with_context(|| format!("Migration {} {} has no down script", migration.version, migration.name))?;
+            tx.batch_execute(down)
+                .await
+                .with_context(|| format!("Failed to revert migration {} {}", migration.version, migration.name))?;
+            tx.execute(
+                &format!("DELETE FROM {} WHERE version = $1", MIGRATIONS_TABLE),
+                &[&migration.version],
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Applies all pending migrations of `dir`. Returns the versions applied by this call.
+    pub async fn migrate_up(&self, dir: impl AsRef<Path>) -> Result<Vec<i64>> {
+        self.migrate_to(dir, i64::MAX).await
+    }
+
+    /// Applies or reverts migrations of `dir` until `version` is the latest applied one.
+    /// Returns the versions applied or reverted by this call.
+    ///
+    /// Fails before applying or reverting any migration if an applied migration was modified or
+    /// removed.
+    pub async fn migrate_to(&self, dir: impl AsRef<Path>, version: i64) -> Result<Vec<i64>> {
+        let migrations = load_migrations(dir)?;
+        let mut client = self.pool.get().await.context("Failed to connect to database")?;
+        self.ensure_migrations_table(&mut client).await?;
+        let applied = self.applied_migrations(&client).await?;
+        for (v, applied) in &applied {
+            match migrations.iter().find(|x| x.version == *v) {
+                Some(migration) => ensure!(
+                    migration.checksum == applied.checksum,
+                    "Migration {} {} has changed since it was applied",
+                    v,
+                    migration.name
+                ),
+                None => bail!("Applied migration {} {} is missing", v, applied.name),
+            }
+        }
+
+        let mut done = vec![];
+        for migration in migrations.iter().filter(|x| x.version <= version) {
+            if self.run_migration(&mut client, migration, true).await? {
+                done.push(migration.version);
+            }
+        }
+        for migration in migrations.iter().rev().filter(|x| x.version > version) {
+            if self.run_migration(&mut client, migration, false).await? {
+                done.push(migration.version);
+            }
+        }
+        Ok(done)
+    }
+
+    /// Returns the state of every migration, either found in `dir` or recorded as applied.
+    pub async fn migration_status(&self, dir: impl AsRef<Path>) -> Result<Vec<MigrationStatus>> {
+        let migrations = load_migrations(dir)?;
+        let mut client = self.pool.get().await.context("Failed to connect to database")?;
+        self.ensure_migrations_table(&mut client).await?;
+        let mut applied = self.applied_migrations(&client).await?;
+
+        let mut status = vec![];
+        for migration in migrations {
+            let (state, applied_at) = match applied.remove(&migration.version) {
+                Some(x) if x.checksum == migration.checksum => (MigrationState::Applied, Some(x.applied_at)),
+                Some(x) => (MigrationState::ChecksumMismatch, Some(x.applied_at)),
+                None => (MigrationState::Pending, None),
+            };
+            status.push(MigrationStatus {
+                version: migration.version,
+                name: migration.name,
+                state,
+                applied_at,
+            });
+        }
+        for (version, x) in applied {
+            status.push(MigrationStatus {
+                version,
+                name: x.name,
+                state: MigrationState::Missing,
+                applied_at: Some(x.applied_at),
+            });
+        }
+        status.sort_by_key(|x| x.version);
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::database::{connect_to_database, database_test_config};
+
+    /// Writes the files to a directory named after the test, so that tests running in parallel
+    /// don't share one.
+    fn write_migrations(test: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}_{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, sql) in files {
+            std::fs::write(dir.join(name), sql).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_load_migrations() {
+        let dir = write_migrations("test_load_migrations", &[
+            ("0002_add_name.sql", "ALTER TABLE t ADD COLUMN name varchar;"),
+            ("0001_create.sql", "CREATE TABLE t (id bigint);"),
+            ("0001_create.down.sql", "DROP TABLE t;"),
+            ("README.md", "ignored"),
+        ]);
+        let migrations = load_migrations(&dir).unwrap();
+        assert_eq!(migrations.len(), 2);
+        assert_eq!(migrations[0].version, 1);
+        assert_eq!(migrations[0].name, "create");
+        assert_eq!(migrations[0].down.as_deref(), Some("DROP TABLE t;"));
+        assert_eq!(migrations[1].version, 2);
+        assert!(migrations[1].down.is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local postgres"]
+    async fn test_migrate_up_and_down() {
+        let dir = write_migrations("test_migrate_up_and_down", &[
+            ("0001_create.sql", "CREATE TABLE migration_test (id bigint);"),
+            ("0001_create.down.sql", "DROP TABLE migration_test;"),
+            ("0002_add_name.sql", "ALTER TABLE migration_test ADD COLUMN name varchar;"),
+            ("0002_add_name.down.sql", "ALTER TABLE migration_test DROP COLUMN name;"),
+        ]);
+        let client = connect_to_database(database_test_config()).await.unwrap();
+        client.migrate_to(&dir, 0).await.unwrap();
+
+        assert_eq!(client.migrate_up(&dir).await.unwrap(), vec![1, 2]);
+        assert!(client.migrate_up(&dir).await.unwrap().is_empty());
+        let status = client.migration_status(&dir).await.unwrap();
+        assert!(status.iter().all(|x| x.state == MigrationState::Applied));
+
+        assert_eq!(client.migrate_to(&dir, 0).await.unwrap(), vec![2, 1]);
+        let status = client.migration_status(&dir).await.unwrap();
+        assert!(status.iter().all(|x| x.state == MigrationState::Pending));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+use eyre::*;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::any::Any;
+use std::fmt::Debug;
+use std::result::Result::Ok;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+
+use crate::libs::datatable::RDataTable;
+use crate::model::Field;
+
+use super::{DatabaseRequest, DbListener, DbNotification, ToSql};
+
+/// A request executed by a `MockDbClient`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MockCall {
+    /// The type name of the request (e.g. `my_service::db::FunUserGetUserReq`)
+    pub request_type: String,
+    pub statement: String,
+    /// The `Debug` representation of each parameter
+    pub params: Vec<String>,
+}
+
+impl MockCall {
+    fn new<T: DatabaseRequest>(req: &T) -> Self {
+        Self {
+            request_type: std::any::type_name::<T>().to_owned(),
+            statement: req.statement().to_owned(),
+            params: req.params().iter().map(|x| format!("{:?}", x)).collect(),
+        }
+    }
+
+    /// A call without parameters, e.g. a statement of a transaction.
+    pub(super) fn statement(request_type: &str, statement: impl Into<String>) -> Self {
+        Self {
+            request_type: request_type.to_owned(),
+            statement: statement.into(),
+            params: vec![],
+        }
+    }
+
+    /// Whether the parameters are equal to `params`, compared by their `Debug` representation.
+    pub fn params_eq(&self, params: &[&(dyn ToSql + Sync)]) -> bool {
+        self.params.len() == params.len() && self.params.iter().zip(params).all(|(a, b)| *a == format!("{:?}", b))
+    }
+}
+
+/// Computes the rows of a request, passed as `&dyn Any` to be downcast to its type.
+type MockHandler = Arc<dyn Fn(&dyn Any) -> Result<Vec<Value>> + Send + Sync>;
+
+enum MockKey {
+    RequestType(String),
+    Statement(String),
+}
+
+#[derive(Default)]
+struct MockState {
+    handlers: Vec<(MockKey, MockHandler)>,
+    calls: Vec<MockCall>,
+    listeners: Vec<(Vec<String>, mpsc::Sender<DbNotification>)>,
+}
+
+/// `MockDbClient` answers requests with registered responses instead of querying a database, so
+/// that handlers can be tested offline. Wrap it in `DbClient::Mock` to pass it to handlers.
+///
+/// Responses are keyed by request type or by statement; when several match, the last registered
+/// one wins and request types take precedence over statements. Requests without a response fail.
+/// Transactions run their closure, and record their `BEGIN` and `COMMIT` or `ROLLBACK`
+/// statements as calls. Clones share their responses and recorded calls.
+///
+/// ```
+/// use endpoint_libs::libs::database::*;
+/// # #[derive(Debug)] struct CountReq;
+/// # impl DatabaseRequest for CountReq {
+/// #     type ResponseRow = CountRow;
+/// #     fn statement(&self) -> &str { "SELECT 1 AS count" }
+/// #     fn params(&self) -> Vec<&(dyn ToSql + Sync)> { vec![] }
+/// # }
+/// # #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, postgres_from_row::FromRow)]
+/// # struct CountRow { count: i64 }
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mock = MockDbClient::new();
+/// mock.on::<CountReq>(vec![CountRow { count: 1 }]);
+/// let client = DbClient::Mock(mock.clone());
+/// let rows = client.execute(CountReq).await.unwrap();
+/// assert_eq!(rows.first(|x| x.count), Some(1));
+/// assert_eq!(mock.calls_of::<CountReq>().len(), 1);
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct MockDbClient {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockDbClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_handler(&self, key: MockKey, handler: MockHandler) -> &Self {
+        self.state.lock().handlers.push((key, handler));
+        self
+    }
+
+    /// Responds to requests of type `T` with `rows`.
+    pub fn on<T: DatabaseRequest>(&self, rows: impl Into<RDataTable<T::ResponseRow>>) -> &Self {
+        let rows: Result<Vec<Value>> = rows
+            .into()
+            .iter()
+            .map(|x| Ok(serde_json::to_value(x)?))
+            .collect();
+        let rows = rows.expect("Failed to serialize mock rows");
+        self.add_handler(
+            MockKey::RequestType(std::any::type_name::<T>().to_owned()),
+            Arc::new(move |_| Ok(rows.clone())),
+        )
+    }
+
+    /// Responds to requests of type `T` with the result of `f`, e.g. to compute rows from the
+    /// request or to return an error.
+    pub fn on_with<T: DatabaseRequest + 'static>(
+        &self,
+        f: impl Fn(&T) -> Result<Vec<T::ResponseRow>> + Send + Sync + 'static,
+    ) -> &Self {
+        self.add_handler(
+            MockKey::RequestType(std::any::type_name::<T>().to_owned()),
+            Arc::new(move |req| {
+                let req = req
+                    .downcast_ref::<T>()
+                    .with_context(|| format!("Mock request is not a {}", std::any::type_name::<T>()))?;
+                f(req)?.iter().map(|x| Ok(serde_json::to_value(x)?)).collect()
+            }),
+        )
+    }
+
+    /// Responds to requests with the given statement with `rows`, whatever their type.
+    pub fn on_statement<R: Serialize>(&self, statement: impl Into<String>, rows: Vec<R>) -> &Self {
+        let rows: Vec<Value> = rows
+            .iter()
+            .map(|x| serde_json::to_value(x).expect("Failed to serialize mock rows"))
+            .collect();
+        self.add_handler(MockKey::Statement(statement.into()), Arc::new(move |_| Ok(rows.clone())))
+    }
+
+    /// Returns every call made so far, in order.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state.lock().calls.clone()
+    }
+
+    /// Returns the calls made with requests of type `T`.
+    pub fn calls_of<T: DatabaseRequest>(&self) -> Vec<MockCall> {
+        let request_type = std::any::type_name::<T>();
+        self.calls().into_iter().filter(|x| x.request_type == request_type).collect()
+    }
+
+    /// Panics unless a request of type `T` was executed with `params`.
+    #[track_caller]
+    pub fn assert_called_with<T: DatabaseRequest>(&self, params: &[&(dyn ToSql + Sync)]) {
+        let calls = self.calls_of::<T>();
+        assert!(
+            calls.iter().any(|x| x.params_eq(params)),
+            "{} was not called with {:?}, calls: {:?}",
+            std::any::type_name::<T>(),
+            params,
+            calls
+        );
+    }
+
+    /// Forgets the recorded calls, keeping the responses.
+    pub fn clear_calls(&self) {
+        self.state.lock().calls.clear();
+    }
+
+    /// Sends a notification to the listeners of `channel`, see `DbClient::listen`.
+    pub fn notify(&self, channel: &str, payload: Value) {
+        let mut state = self.state.lock();
+        state.listeners.retain(|(channels, tx)| {
+            if !channels.iter().any(|x| x == channel) {
+                return !tx.is_closed();
+            }
+            let notification = DbNotification {
+                channel: channel.to_owned(),
+                payload: payload.clone(),
+                process_id: 0,
+            };
+            tx.try_send(notification).is_ok()
+        });
+    }
+
+    pub(super) fn record(&self, call: MockCall) {
+        self.state.lock().calls.push(call);
+    }
+
+    fn respond(&self, call: MockCall, req: &dyn Any) -> Result<Vec<Value>> {
+        let handler = {
+            let mut state = self.state.lock();
+            state.calls.push(call.clone());
+            let by_type = state
+                .handlers
+                .iter()
+                .rev()
+                .find(|(key, _)| matches!(key, MockKey::RequestType(x) if *x == call.request_type));
+            let by_statement = || {
+                state
+                    .handlers
+                    .iter()
+                    .rev()
+                    .find(|(key, _)| matches!(key, MockKey::Statement(x) if *x == call.statement))
+            };
+            by_type.or_else(by_statement).map(|(_, handler)| handler.clone())
+        };
+        let handler = handler.with_context(|| {
+            format!("No mock response for {}: {}", call.request_type, call.statement)
+        })?;
+        handler(req)
+    }
+
+    pub async fn execute<T: DatabaseRequest + Debug + 'static>(&self, req: T) -> Result<RDataTable<T::ResponseRow>> {
+        let rows = self.respond(MockCall::new(&req), &req)?;
+        let mut response = RDataTable::with_capacity(rows.len());
+        for row in rows {
+            response.push(serde_json::from_value(row).context("Mock row doesn't match the response row")?);
+        }
+        Ok(response)
+    }
+
+    /// Records the copy as a call with a `COPY` statement and no parameters, and returns the
+    /// number of rows.
+    pub async fn copy_in<R: Serialize>(
+        &self,
+        table: &str,
+        columns: &[Field],
+        rows: impl IntoIterator<Item = R>,
+    ) -> Result<u64> {
+        let names: Vec<&str> = columns.iter().map(|x| x.name.as_str()).collect();
+        let statement = format!("COPY {} ({}) FROM STDIN BINARY", table, names.join(", "));
+        self.record(MockCall::statement("copy_in", statement));
+        Ok(rows.into_iter().count() as u64)
+    }
+
+    /// Returns a listener receiving the notifications sent with [`MockDbClient::notify`].
+    pub fn listen(&self, channels: &[&str]) -> DbListener {
+        let (tx, rx) = mpsc::channel(1024);
+        let channels = channels.iter().map(|x| x.to_string()).collect();
+        self.state.lock().listeners.push((channels, tx));
+        // always connected
+        DbListener::from_receiver(rx, watch::channel(true).1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::database::DbClient;
+
+    #[derive(Debug)]
+    struct UserReq {
+        user_id: i64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, postgres_from_row::FromRow)]
+    struct UserRow {
+        user_id: i64,
+        name: String,
+    }
+
+    impl DatabaseRequest for UserReq {
+        type ResponseRow = UserRow;
+        fn statement(&self) -> &str {
+            "SELECT * FROM api.fun_user_get_user(a_user_id => $1::bigint);"
+        }
+        fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+            vec![&self.user_id]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_db_client() {
+        let mock = MockDbClient::new();
+        let client = DbClient::Mock(mock.clone());
+        assert!(client.execute(UserReq { user_id: 1 }).await.is_err());
+
+        mock.on_with::<UserReq>(|req| {
+            Ok(vec![UserRow {
+                user_id: req.user_id,
+                name: "alice".to_owned(),
+            }])
+        });
+        let rows = client.execute(UserReq { user_id: 2 }).await.unwrap();
+        assert_eq!(rows.first(|x| x.user_id), Some(2));
+        mock.assert_called_with::<UserReq>(&[&2i64]);
+        assert_eq!(mock.calls().len(), 2);
+
+        mock.clear_calls();
+        let result: Result<()> = client
+            .transaction(|tx| async move {
+                tx.execute(UserReq { user_id: 3 }).await?;
+                bail!("rollback")
+            })
+            .await;
+        assert!(result.is_err());
+        let statements: Vec<String> = mock.calls().into_iter().map(|x| x.statement).collect();
+        assert_eq!(statements[0], "BEGIN ISOLATION LEVEL READ COMMITTED");
+        assert_eq!(statements[2], "ROLLBACK");
+        mock.assert_called_with::<UserReq>(&[&3i64]);
+
+        let mut listener = client.listen(&["user"]).await.unwrap();
+        mock.notify("user", serde_json::json!({"userId": 2}));
+        assert_eq!(listener.recv().await.unwrap().payload["userId"], 2);
+    }
+}
@@ -0,0 +1,243 @@
+use eyre::*;
+use futures::future::poll_fn;
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::result::Result::Ok;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_postgres::{AsyncMessage, Connection, NoTls};
+use tokio_postgres_rustls::MakeRustlsConnect;
+use tracing::*;
+
+use crate::libs::signal::CANCELLATION_TOKEN;
+use crate::libs::toolbox::ArcToolbox;
+use crate::libs::ws::SubscribeManager;
+
+use super::copy::quote_ident;
+use super::PooledDbClient;
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+const NOTIFICATION_BUFFER: usize = 1024;
+
+/// A notification received on a `LISTEN`ed channel.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DbNotification {
+    pub channel: String,
+    /// The payload decoded as JSON, or a JSON string if it is not valid JSON
+    pub payload: Value,
+    /// The process id of the backend that sent the notification
+    pub process_id: i32,
+}
+
+/// `DbListener` is a stream of the notifications sent to the channels it listens on.
+///
+/// It holds a dedicated connection, outside of the pool, and reconnects with backoff if the
+/// connection is lost. Notifications sent while it is disconnected are lost, so consumers that
+/// can't miss changes should re-read the state after a reconnect, see [`DbListener::connected`].
+/// The connection is closed when the listener is dropped or on `CANCELLATION_TOKEN`.
+///
+/// Up to 1024 notifications are buffered. When the consumer falls further behind, the connection
+/// is no longer read and the notifications queue up in the database instead, until its
+/// notification queue is full and `NOTIFY` fails.
+pub struct DbListener {
+    rx: mpsc::Receiver<DbNotification>,
+    connected: watch::Receiver<bool>,
+}
+
+impl DbListener {
+    pub(super) fn from_receiver(rx: mpsc::Receiver<DbNotification>, connected: watch::Receiver<bool>) -> Self {
+        Self { rx, connected }
+    }
+
+    /// Returns a receiver of whether the listener is connected, which becomes true once it listens
+    /// on all channels, initially and after each reconnect.
+    pub fn connected(&self) -> watch::Receiver<bool> {
+        self.connected.clone()
+    }
+
+    pub async fn recv(&mut self) -> Option<DbNotification> {
+        self.rx.recv().await
+    }
+
+    /// Publishes the payload of each notification to all subscribers of the topic returned by
+    /// `topic`, skipping notifications it returns `None` for.
+    pub fn forward_to_subscribers<Key>(
+        mut self,
+        toolbox: ArcToolbox,
+        manager: Arc<SubscribeManager<Key>>,
+        topic: impl Fn(&DbNotification) -> Option<Key> + Send + 'static,
+    ) -> JoinHandle<()>
+    where
+        Key: Hash + Eq + Into<u32> + Send + Sync + 'static,
+    {
+        tokio::spawn(async move {
+            while let Some(notification) = self.recv().await {
+                if let Some(topic) = topic(&notification) {
+                    manager.publish_to_all(&toolbox, topic, &notification.payload);
+                }
+            }
+        })
+    }
+}
+
+impl Stream for DbListener {
+    type Item = DbNotification;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+fn decode_notification(notification: tokio_postgres::Notification) -> DbNotification {
+    let payload = serde_json::from_str(notification.payload())
+        .unwrap_or_else(|_| Value::String(notification.payload().to_owned()));
+    DbNotification {
+        channel: notification.channel().to_owned(),
+        payload,
+        process_id: notification.process_id(),
+    }
+}
+
+/// Polls the connection in the background, forwarding its messages until it closes. It stops
+/// polling while the channel is full, leaving the messages to the socket and the database.
+fn spawn_driver<S, T>(
+    mut connection: Connection<S, T>,
+) -> (JoinHandle<Result<(), tokio_postgres::Error>>, mpsc::Receiver<AsyncMessage>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (message_tx, message_rx) = mpsc::channel(NOTIFICATION_BUFFER);
+    let driver = tokio::spawn(async move {
+        while let Some(message) = poll_fn(|cx| connection.poll_message(cx)).await {
+            if message_tx.send(message?).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+    (driver, message_rx)
+}
+
+/// Listens until the connection is lost (`Err`) or the listener is no longer needed (`Ok`).
+async fn listen_once(
+    config: &tokio_postgres::Config,
+    tls: Option<&MakeRustlsConnect>,
+    channels: &[String],
+    tx: &mpsc::Sender<DbNotification>,
+    connected: &watch::Sender<bool>,
+    delay: &mut Duration,
+) -> Result<()> {
+    let (client, (driver, mut message_rx)) = match tls {
+        Some(tls) => {
+            let (client, connection) = config.connect(tls.clone()).await?;
+            (client, spawn_driver(connection))
+        }
+        None => {
+            let (client, connection) = config.connect(NoTls).await?;
+            (client, spawn_driver(connection))
+        }
+    };
+    let statements: Vec<String> = channels.iter().map(|x| format!("LISTEN {};", quote_ident(x))).collect();
+    client.batch_execute(&statements.join(" ")).await?;
+    info!("Listening on database channels {:?}", channels);
+    connected.send_replace(true);
+    *delay = MIN_RECONNECT_DELAY;
+
+    loop {
+        tokio::select! {
+            message = message_rx.recv() => match message {
+                Some(AsyncMessage::Notification(notification)) => {
+                    if tx.send(decode_notification(notification)).await.is_err() {
+                        return Ok(());
+                    }
+                }
+                Some(AsyncMessage::Notice(notice)) => debug!("Database notice: {}", notice),
+                Some(_) => {}
+                None => {
+                    driver.await??;
+                    bail!("Database connection closed");
+                }
+            },
+            _ = tx.closed() => return Ok(()),
+            _ = CANCELLATION_TOKEN.cancelled() => return Ok(()),
+        }
+    }
+}
+
+async fn run_listener(
+    config: Arc<tokio_postgres::Config>,
+    tls: Option<MakeRustlsConnect>,
+    channels: Vec<String>,
+    tx: mpsc::Sender<DbNotification>,
+    connected: watch::Sender<bool>,
+) {
+    let mut delay = MIN_RECONNECT_DELAY;
+    loop {
+        let result = listen_once(&config, tls.as_ref(), &channels, &tx, &connected, &mut delay).await;
+        connected.send_replace(false);
+        match result {
+            Ok(()) => return,
+            Err(err) => warn!("Database listener disconnected, reconnecting in {:?}: {:?}", delay, err),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = tx.closed() => return,
+            _ = CANCELLATION_TOKEN.cancelled() => return,
+        }
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+impl PooledDbClient {
+    /// Starts listening on `channels` with a dedicated connection, see [`DbListener`].
+    pub fn listen(&self, channels: &[&str]) -> DbListener {
+        let (tx, rx) = mpsc::channel(NOTIFICATION_BUFFER);
+        let (connected_tx, connected) = watch::channel(false);
+        let channels = channels.iter().map(|x| x.to_string()).collect();
+        tokio::spawn(run_listener(self.pg_config.clone(), self.tls.clone(), channels, tx, connected_tx));
+        DbListener::from_receiver(rx, connected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use super::*;
+    use crate::libs::database::{connect_to_database, database_test_config, DbClient};
+
+    #[tokio::test]
+    #[ignore = "requires a local postgres"]
+    async fn test_listen() {
+        let pooled = connect_to_database(database_test_config()).await.unwrap();
+        let mut listener = pooled.listen(&["listen_test"]);
+        let mut connected = listener.connected();
+        tokio::time::timeout(Duration::from_secs(5), connected.wait_for(|x| *x))
+            .await
+            .unwrap()
+            .unwrap();
+        let client = DbClient::from(pooled);
+        // the listener connects in the background, so notify until it is listening
+        let notification = loop {
+            client
+                .transaction(|tx| async move { tx.batch_execute(r#"NOTIFY listen_test, '{"id": 1}'"#).await })
+                .await
+                .unwrap();
+            if let Ok(x) = tokio::time::timeout(Duration::from_millis(100), listener.next()).await {
+                break x.unwrap();
+            }
+        };
+        assert_eq!(notification.channel, "listen_test");
+        assert_eq!(notification.payload, json!({"id": 1}));
+    }
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio_postgres::error::SqlState;
+
+/// `QueryPolicy` controls how `PooledDbClient::execute` runs a request: how long it may take and
+/// which failures are retried.
+///
+/// A client has a default policy, which a request can override with [`DatabaseRequest::query_policy`].
+///
+/// [`DatabaseRequest::query_policy`]: super::DatabaseRequest::query_policy
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryPolicy {
+    /// Timeout of preparing the statement
+    pub prepare_timeout: Duration,
+    /// Timeout of executing the statement
+    pub query_timeout: Duration,
+    /// How many times a failed attempt is retried, besides the one retry after invalidating stale
+    /// prepared statements
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on every following retry
+    pub backoff: Duration,
+    /// Upper bound of the delay between retries
+    pub max_backoff: Duration,
+    /// SQLSTATE codes (e.g. `40001`) or classes (e.g. `08`) of errors to retry. Connection errors
+    /// are only retried for [idempotent] requests, unless no connection could be checked out of the
+    /// pool, which counts as class `08` too.
+    ///
+    /// [idempotent]: super::DatabaseRequest::idempotent
+    pub retryable_sqlstates: Vec<String>,
+}
+
+impl Default for QueryPolicy {
+    fn default() -> Self {
+        Self {
+            prepare_timeout: Duration::from_secs(20),
+            query_timeout: Duration::from_secs(20),
+            max_retries: 1,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            retryable_sqlstates: vec![
+                SqlState::T_R_SERIALIZATION_FAILURE.code().to_owned(),
+                SqlState::T_R_DEADLOCK_DETECTED.code().to_owned(),
+                // connection exceptions
+                "08".to_owned(),
+            ],
+        }
+    }
+}
+
+impl QueryPolicy {
+    /// A policy that only retries once after invalidating stale prepared statements, with the default
+    /// timeouts.
+    pub fn no_retry() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Returns whether an error with the given SQLSTATE code should be retried. A connection error
+    /// (class `08`) may come after the server applied the request, so it is only retried when the
+    /// request is idempotent.
+    pub fn is_retryable_sqlstate(&self, code: &str, idempotent: bool) -> bool {
+        if code.starts_with("08") && !idempotent {
+            return false;
+        }
+        self.retryable_sqlstates.iter().any(|x| code.starts_with(x.as_str()))
+    }
+
+    /// Returns the delay before the `retry`-th retry, starting from 0.
+    pub fn backoff_for(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+/// Counters of the requests executed by a `PooledDbClient`, shared by its clones.
+#[derive(Debug, Default)]
+pub(super) struct QueryMetrics {
+    queries: AtomicU64,
+    retries: AtomicU64,
+    timeouts: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl QueryMetrics {
+    pub(super) fn record_query(&self) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(super) fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(super) fn record_timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(super) fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(super) fn snapshot(&self) -> QueryMetricsSnapshot {
+        QueryMetricsSnapshot {
+            queries: self.queries.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A point in time copy of the query counters of a `PooledDbClient`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryMetricsSnapshot {
+    /// Requests executed, regardless of their outcome
+    pub queries: u64,
+    /// Attempts retried after a retryable error
+    pub retries: u64,
+    /// Attempts that timed out
+    pub timeouts: u64,
+    /// Requests that failed after all retries
+    pub failures: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_policy() {
+        let policy = QueryPolicy::default();
+        assert!(policy.is_retryable_sqlstate("40001", false));
+        assert!(policy.is_retryable_sqlstate("40P01", false));
+        assert!(policy.is_retryable_sqlstate("08006", true));
+        assert!(!policy.is_retryable_sqlstate("08006", false));
+        assert!(!policy.is_retryable_sqlstate("23505", true));
+        assert_eq!(policy.backoff_for(0), Duration::from_millis(50));
+        assert_eq!(policy.backoff_for(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff_for(10), Duration::from_secs(2));
+    }
+}
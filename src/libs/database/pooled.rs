@@ -2,6 +2,8 @@ use dashmap::DashMap;
 use deadpool_postgres::Runtime;
 use deadpool_postgres::*;
 use eyre::*;
+use futures::stream::{self, BoxStream};
+use futures::{StreamExt, TryStreamExt};
 use postgres_from_row::FromRow;
 use secrecy::ExposeSecret;
 use std::collections::hash_map::DefaultHasher;
@@ -10,9 +12,10 @@ use std::hash::Hash;
 use std::hash::Hasher;
 use std::result::Result::Ok;
 use std::sync::Arc;
-use std::time::Duration;
 pub use tokio_postgres::types::ToSql;
+use tokio_postgres::error::SqlState;
 use tokio_postgres::Statement;
+use tokio_postgres_rustls::MakeRustlsConnect;
 pub use tokio_postgres::{NoTls, Row, ToStatement};
 use tracing::*;
 
@@ -20,12 +23,17 @@ use crate::libs::datatable::RDataTable;
 
 use super::DatabaseConfig;
 use super::DatabaseRequest;
+use super::{QueryMetrics, QueryMetricsSnapshot, QueryPolicy};
 
 #[derive(Clone)]
 pub struct PooledDbClient {
-    pool: Pool,
+    pub(super) pool: Pool,
     prepared_stmts: Arc<DashMap<String, Statement>>,
     conn_hash: u64,
+    query_policy: QueryPolicy,
+    metrics: Arc<QueryMetrics>,
+    pub(super) pg_config: Arc<tokio_postgres::Config>,
+    pub(super) tls: Option<MakeRustlsConnect>,
 }
 impl PooledDbClient {
     #[deprecated]
@@ -46,65 +54,197 @@ impl PooledDbClient {
             .await?)
     }
 
+    /// Executes the request according to its [`QueryPolicy`], or the client's one if it has none.
     pub async fn execute<T: DatabaseRequest + Debug>(
         &self,
         req: T,
     ) -> Result<RDataTable<T::ResponseRow>> {
-        let mut error = None;
-        for _ in 0..2 {
-            let begin = std::time::Instant::now();
-            let client = self
+        self.execute_ref(&req).await
+    }
+
+    pub(super) async fn execute_ref<T: DatabaseRequest + Debug>(&self, req: &T) -> Result<RDataTable<T::ResponseRow>> {
+        let policy = req.query_policy().unwrap_or_else(|| self.query_policy.clone());
+        self.metrics.record_query();
+        let mut retry = 0;
+        let mut invalidated = false;
+        loop {
+            match self.execute_once(req, &policy).await {
+                Ok(response) => return Ok(response),
+                // the schema changed under the cached statements, which is fixed by preparing them again
+                Err(err) if !invalidated && is_stale_statement(&err) => {
+                    warn!("Database has been updated. Cleaning cache and retrying query");
+                    self.invalidate_statements();
+                    invalidated = true;
+                    self.metrics.record_retry();
+                }
+                Err(err) if retry < policy.max_retries && is_retryable(&policy, &err, req.idempotent()) => {
+                    let delay = policy.backoff_for(retry);
+                    warn!("Retrying database query in {:?} after error: {:?}", delay, err);
+                    self.metrics.record_retry();
+                    retry += 1;
+                    tokio::time::sleep(delay).await;
+                }
+                Err(err) => {
+                    self.metrics.record_failure();
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    async fn execute_once<T: DatabaseRequest + Debug>(
+        &self,
+        req: &T,
+        policy: &QueryPolicy,
+    ) -> Result<RDataTable<T::ResponseRow>> {
+        let begin = std::time::Instant::now();
+        let client = self
+            .pool
+            .get()
+            .await
+            .context("Failed to connect to database")?;
+        let statement = match tokio::time::timeout(policy.prepare_timeout, client.prepare_cached(req.statement())).await
+        {
+            Ok(statement) => statement?,
+            Err(_) => {
+                self.metrics.record_timeout();
+                bail!("timeout preparing statement: {}", req.statement());
+            }
+        };
+        let rows = match tokio::time::timeout(policy.query_timeout, client.query(&statement, &req.params())).await {
+            Ok(rows) => rows?,
+            Err(_) => {
+                self.metrics.record_timeout();
+                bail!("timeout executing statement: {}, params: {:?}", req.statement(), req.params());
+            }
+        };
+        let dur = begin.elapsed();
+        debug!(
+            "Database query took {}.{:03} seconds: {:?}",
+            dur.as_secs(),
+            dur.subsec_millis(),
+            req
+        );
+        let mut response = RDataTable::with_capacity(rows.len());
+        for row in rows {
+            response.push(T::ResponseRow::try_from_row(&row)?);
+        }
+        Ok(response)
+    }
+
+    /// Executes the request and yields its rows as they arrive instead of collecting them, so that
+    /// large results can be processed incrementally. The connection is held until the stream is
+    /// exhausted or dropped.
+    ///
+    /// The request's [`QueryPolicy`] timeouts apply to preparing the statement and receiving the first
+    /// response; streamed requests are not retried.
+    pub fn execute_stream<T>(&self, req: T) -> BoxStream<'static, Result<T::ResponseRow>>
+    where
+        T: DatabaseRequest + Debug + 'static,
+    {
+        let this = self.clone();
+        stream::once(async move {
+            let policy = req.query_policy().unwrap_or_else(|| this.query_policy.clone());
+            this.metrics.record_query();
+            let client = this
                 .pool
                 .get()
                 .await
                 .context("Failed to connect to database")?;
-            let statement =
-                tokio::time::timeout(Duration::from_secs(20), client.prepare_cached(req.statement()))
-                    .await
-                    .context("timeout preparing statement")??;
-            let rows = match tokio::time::timeout(
-                Duration::from_secs(20),
-                client.query(&statement, &req.params()),
-            )
-            .await
-            .context(format!("timeout executing statement: {}, params: {:?}", req.statement(), req.params()))?
+            let statement = match tokio::time::timeout(policy.prepare_timeout, client.prepare_cached(req.statement()))
+                .await
             {
-                Ok(rows) => rows,
-                Err(err) => {
-                    let reason = err.to_string();
-                    if reason.contains("cache lookup failed for type")
-                        || reason.contains("cached plan must not change result type")
-                        || reason.contains("prepared statement")
-                    {
-                        warn!("Database has been updated. Cleaning cache and retrying query");
-                        self.prepared_stmts.clear();
-                        error = Some(err);
-                        continue;
-                    }
-                    return Err(err.into());
+                Ok(statement) => statement?,
+                Err(_) => {
+                    this.metrics.record_timeout();
+                    bail!("timeout preparing statement: {}", req.statement());
                 }
             };
-            let dur = begin.elapsed();
-            debug!(
-                "Database query took {}.{:03} seconds: {:?}",
-                dur.as_secs(),
-                dur.subsec_millis(),
-                req
-            );
-            let mut response = RDataTable::with_capacity(rows.len());
-            for row in rows {
-                response.push(T::ResponseRow::try_from_row(&row)?);
-            }
-            return Ok(response);
-        }
-        Err(error.unwrap().into())
+            let query = client.query_raw(&statement, req.params());
+            let rows = match tokio::time::timeout(policy.query_timeout, query).await {
+                Ok(rows) => rows?,
+                Err(_) => {
+                    this.metrics.record_timeout();
+                    bail!("timeout executing statement: {}, params: {:?}", req.statement(), req.params());
+                }
+            };
+            debug!("Database query streaming: {:?}", req);
+            let rows = stream::try_unfold((client, Box::pin(rows)), |(client, mut rows)| async move {
+                match rows.try_next().await? {
+                    Some(row) => Ok(Some((T::ResponseRow::try_from_row(&row)?, (client, rows)))),
+                    None => Ok(None),
+                }
+            });
+            Ok(rows)
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Drops the prepared statements of all connections, so that they are prepared again.
+    pub fn invalidate_statements(&self) {
+        self.prepared_stmts.clear();
+        self.pool.manager().statement_caches.clear();
+    }
+
+    /// Replaces the default [`QueryPolicy`] of requests executed by this client.
+    pub fn with_query_policy(mut self, policy: QueryPolicy) -> Self {
+        self.query_policy = policy;
+        self
+    }
+    pub fn query_policy(&self) -> &QueryPolicy {
+        &self.query_policy
+    }
+    /// Returns the query counters, shared by all clones of this client.
+    pub fn query_metrics(&self) -> QueryMetricsSnapshot {
+        self.metrics.snapshot()
     }
     pub fn conn_hash(&self) -> u64 {
         self.conn_hash
     }
 }
 
+fn is_stale_statement(err: &Error) -> bool {
+    let Some(err) = err.downcast_ref::<tokio_postgres::Error>() else {
+        return false;
+    };
+    let reason = err.to_string();
+    reason.contains("cache lookup failed for type")
+        || reason.contains("cached plan must not change result type")
+        || reason.contains("prepared statement")
+}
+
+fn is_retryable(policy: &QueryPolicy, err: &Error, idempotent: bool) -> bool {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<PoolError>() {
+            // no connection could be checked out, so the request was never sent
+            let code = match err {
+                PoolError::Timeout(_) => SqlState::CONNECTION_FAILURE.code(),
+                PoolError::Backend(err) => err.code().unwrap_or(&SqlState::CONNECTION_FAILURE).code(),
+                _ => return false,
+            };
+            return policy.is_retryable_sqlstate(code, true);
+        }
+        if let Some(err) = cause.downcast_ref::<tokio_postgres::Error>() {
+            return match err.code() {
+                Some(code) => policy.is_retryable_sqlstate(code.code(), idempotent),
+                // the connection was reset before the server reported an error
+                None => {
+                    err.is_closed() && policy.is_retryable_sqlstate(SqlState::CONNECTION_FAILURE.code(), idempotent)
+                }
+            };
+        }
+    }
+    false
+}
+
 pub async fn connect_to_database(config: DatabaseConfig) -> Result<PooledDbClient> {
+    let query_policy = config.query_policy.unwrap_or_default();
+    let tls = config.tls.as_ref().map(|x| x.make_connector()).transpose()?;
+    ensure!(
+        tls.is_some() || !matches!(config.ssl_mode, Some(SslMode::Require)),
+        "ssl_mode Require needs a tls configuration"
+    );
     let config = Config {
         user: config.user,
         password: config.password.map(|s| s.expose_secret().clone()),
@@ -121,11 +261,9 @@ pub async fn connect_to_database(config: DatabaseConfig) -> Result<PooledDbClien
         keepalives_idle: config.keepalives_idle,
         target_session_attrs: config.target_session_attrs,
         channel_binding: config.channel_binding,
-        manager: config.manager.or_else(|| {
-            Some(ManagerConfig {
-                recycling_method: RecyclingMethod::Fast,
-            })
-        }),
+        manager: config.manager.or(Some(ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        })),
         pool: config.pool,
         ..Default::default()
     };
@@ -141,10 +279,75 @@ pub async fn connect_to_database(config: DatabaseConfig) -> Result<PooledDbClien
     config.dbname.hash(&mut hasher);
     let conn_hash = hasher.finish();
 
-    let pool = config.create_pool(Some(Runtime::Tokio1), NoTls)?;
+    let pg_config = config.get_pg_config()?;
+    let pool = match &tls {
+        Some(tls) => config.create_pool(Some(Runtime::Tokio1), tls.clone())?,
+        None => config.create_pool(Some(Runtime::Tokio1), NoTls)?,
+    };
     Ok(PooledDbClient {
         pool,
         prepared_stmts: Arc::new(Default::default()),
         conn_hash,
+        query_policy,
+        metrics: Arc::new(Default::default()),
+        pg_config: Arc::new(pg_config),
+        tls,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::libs::database::{database_test_config, spawn_thread_db_client, DbClient};
+
+    #[derive(Debug)]
+    struct SeriesReq(i64);
+
+    #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+    struct SeriesRow {
+        n: i64,
+    }
+
+    impl DatabaseRequest for SeriesReq {
+        type ResponseRow = SeriesRow;
+        fn statement(&self) -> &str {
+            "SELECT n FROM generate_series(1::bigint, $1::bigint) AS n"
+        }
+        fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+            vec![&self.0]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_pool_errors() {
+        let config = Config {
+            dbname: Some("test".to_owned()),
+            pool: Some(PoolConfig {
+                max_size: 0,
+                timeouts: Timeouts::wait_millis(0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let pool = config.create_pool(Some(Runtime::Tokio1), tokio_postgres::NoTls).unwrap();
+        let timeout = pool.get().await.context("Failed to connect to database").unwrap_err();
+        assert!(is_retryable(&QueryPolicy::default(), &timeout, false));
+        pool.close();
+        let closed = pool.get().await.context("Failed to connect to database").unwrap_err();
+        assert!(!is_retryable(&QueryPolicy::default(), &closed, true));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local postgres"]
+    async fn test_execute_stream() {
+        let pooled = connect_to_database(database_test_config()).await.unwrap();
+        let threaded = spawn_thread_db_client(pooled.clone()).unwrap();
+        for client in [DbClient::from(pooled), DbClient::from(threaded)] {
+            let rows: Vec<SeriesRow> = client.execute_stream(SeriesReq(1000)).try_collect().await.unwrap();
+            assert_eq!(rows.len(), 1000);
+            assert_eq!(rows.last().unwrap().n, 1000);
+        }
+    }
+}
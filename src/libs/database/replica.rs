@@ -0,0 +1,300 @@
+use eyre::*;
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::result::Result::Ok;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tracing::*;
+
+use crate::libs::datatable::RDataTable;
+use crate::libs::signal::CANCELLATION_TOKEN;
+
+use super::{connect_to_database, DatabaseConfig, DatabaseRequest, PooledDbClient};
+
+/// Replication lag of a standby in milliseconds, 0 if it replayed everything it received.
+const REPLICATION_LAG_QUERY: &str = "SELECT CASE
+    WHEN NOT pg_is_in_recovery() OR pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0
+    ELSE COALESCE(EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp()) * 1000, 0)
+END::bigint";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplicationConfig {
+    /// Replicas lagging behind the primary by more than this are not used for reads
+    pub max_lag: Duration,
+    /// How often the health and lag of the replicas are checked
+    pub health_check_interval: Duration,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            max_lag: Duration::from_secs(5),
+            health_check_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+struct Replica {
+    client: PooledDbClient,
+    healthy: AtomicBool,
+    lag_ms: AtomicI64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ReplicaStatus {
+    /// The index of the replica in the configured replicas
+    pub index: usize,
+    pub healthy: bool,
+    pub lag_ms: i64,
+    /// Whether the replica currently receives reads
+    pub available: bool,
+}
+
+/// `ReplicatedDbClient` sends writes to a primary and spreads read-only requests (see
+/// [`DatabaseRequest::read_only`]) over replicas with round-robin.
+///
+/// Replicas that fail a health check or lag behind by more than `max_lag` are skipped until they
+/// recover. Reads fall back to the primary if no replica is available, the chosen replica can't be
+/// reached or refuses queries (SQLSTATE classes `08`, `53` and `57P`), or the query conflicted with
+/// recovery on the replica.
+#[derive(Clone)]
+pub struct ReplicatedDbClient {
+    primary: PooledDbClient,
+    replicas: Arc<Vec<Replica>>,
+    next: Arc<AtomicUsize>,
+    max_lag_ms: i64,
+}
+
+impl ReplicatedDbClient {
+    pub fn primary(&self) -> &PooledDbClient {
+        &self.primary
+    }
+
+    pub fn replica_status(&self) -> Vec<ReplicaStatus> {
+        self.replicas
+            .iter()
+            .enumerate()
+            .map(|(index, x)| ReplicaStatus {
+                index,
+                healthy: x.healthy.load(Ordering::Relaxed),
+                lag_ms: x.lag_ms.load(Ordering::Relaxed),
+                available: self.is_available(x),
+            })
+            .collect()
+    }
+
+    fn is_available(&self, replica: &Replica) -> bool {
+        replica.healthy.load(Ordering::Relaxed) && replica.lag_ms.load(Ordering::Relaxed) <= self.max_lag_ms
+    }
+
+    /// Picks the next available replica, if any.
+    fn pick_replica(&self) -> Option<&Replica> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..self.replicas.len())
+            .map(|i| &self.replicas[(start + i) % self.replicas.len()])
+            .find(|x| self.is_available(x))
+    }
+
+    pub async fn execute<T: DatabaseRequest + Debug>(&self, req: T) -> Result<RDataTable<T::ResponseRow>> {
+        if !req.read_only() {
+            return self.primary.execute(req).await;
+        }
+        let Some(replica) = self.pick_replica() else {
+            return self.primary.execute(req).await;
+        };
+        match replica.client.execute_ref(&req).await {
+            Ok(rows) => Ok(rows),
+            Err(err) => match replica_failure(&err) {
+                ReplicaFailure::None => Err(err),
+                ReplicaFailure::RecoveryConflict => {
+                    warn!("Query conflicted with recovery on replica, falling back to primary: {:?}", err);
+                    self.primary.execute(req).await
+                }
+                ReplicaFailure::Unavailable => {
+                    warn!("Replica failed, falling back to primary: {:?}", err);
+                    replica.healthy.store(false, Ordering::Relaxed);
+                    self.primary.execute(req).await
+                }
+            },
+        }
+    }
+
+    pub fn execute_stream<T>(&self, req: T) -> BoxStream<'static, Result<T::ResponseRow>>
+    where
+        T: DatabaseRequest + Debug + 'static,
+    {
+        if !req.read_only() {
+            return self.primary.execute_stream(req);
+        }
+        match self.pick_replica() {
+            Some(replica) => replica.client.execute_stream(req),
+            None => self.primary.execute_stream(req),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReplicaFailure {
+    /// The error would happen on the primary as well
+    None,
+    /// The replica cancelled the query because of a conflict with the WAL it replays
+    RecoveryConflict,
+    /// The replica can't serve queries right now
+    Unavailable,
+}
+
+fn replica_failure(err: &Error) -> ReplicaFailure {
+    let code = err
+        .chain()
+        .find_map(|x| x.downcast_ref::<tokio_postgres::Error>())
+        .and_then(|x| x.code());
+    sqlstate_failure(code.map(|x| x.code()))
+}
+
+fn sqlstate_failure(code: Option<&str>) -> ReplicaFailure {
+    match code {
+        // the connection failed before the server reported an error
+        None => ReplicaFailure::Unavailable,
+        // serialization_failure and deadlock_detected are how a standby reports recovery conflicts
+        Some("40001" | "40P01") => ReplicaFailure::RecoveryConflict,
+        // connection exceptions, insufficient resources and operator interventions (e.g. 57P03
+        // cannot_connect_now while the standby starts up)
+        Some(code) if code.starts_with("08") || code.starts_with("53") || code.starts_with("57P") => {
+            ReplicaFailure::Unavailable
+        }
+        Some(_) => ReplicaFailure::None,
+    }
+}
+
+async fn check_replica(replica: &Replica) -> Result<i64> {
+    let client = replica.client.pool.get().await?;
+    let lag_ms: i64 = client.query_one(REPLICATION_LAG_QUERY, &[]).await?.try_get(0)?;
+    Ok(lag_ms)
+}
+
+async fn run_health_checks(replicas: Weak<Vec<Replica>>, interval: Duration) {
+    loop {
+        let Some(replicas) = replicas.upgrade() else {
+            return;
+        };
+        for (index, replica) in replicas.iter().enumerate() {
+            match check_replica(replica).await {
+                Ok(lag_ms) => {
+                    replica.lag_ms.store(lag_ms, Ordering::Relaxed);
+                    if !replica.healthy.swap(true, Ordering::Relaxed) {
+                        info!("Replica {} is healthy again", index);
+                    }
+                }
+                Err(err) => {
+                    if replica.healthy.swap(false, Ordering::Relaxed) {
+                        warn!("Replica {} failed its health check: {:?}", index, err);
+                    }
+                }
+            }
+        }
+        drop(replicas);
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = CANCELLATION_TOKEN.cancelled() => return,
+        }
+    }
+}
+
+/// Connects to the primary and the replicas, and starts checking the replicas in the background.
+/// Replicas start as unhealthy until their first health check passes.
+pub async fn connect_to_replicated_database(
+    primary: DatabaseConfig,
+    replicas: Vec<DatabaseConfig>,
+    config: ReplicationConfig,
+) -> Result<ReplicatedDbClient> {
+    let primary = connect_to_database(primary).await?;
+    let mut clients = vec![];
+    for replica in replicas {
+        clients.push(Replica {
+            client: connect_to_database(replica).await?,
+            healthy: AtomicBool::new(false),
+            lag_ms: AtomicI64::new(0),
+        });
+    }
+    let replicas = Arc::new(clients);
+    tokio::spawn(run_health_checks(Arc::downgrade(&replicas), config.health_check_interval));
+    Ok(ReplicatedDbClient {
+        primary,
+        replicas,
+        next: Arc::new(AtomicUsize::new(0)),
+        max_lag_ms: config.max_lag.as_millis() as i64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::database::{database_test_config, DbClient, ToSql};
+
+    #[derive(Debug)]
+    struct RecoveryReq;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, postgres_from_row::FromRow)]
+    struct RecoveryRow {
+        in_recovery: bool,
+    }
+
+    impl DatabaseRequest for RecoveryReq {
+        type ResponseRow = RecoveryRow;
+        fn statement(&self) -> &str {
+            "SELECT pg_is_in_recovery() AS in_recovery"
+        }
+        fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+            vec![]
+        }
+        fn read_only(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_sqlstate_failure() {
+        assert_eq!(sqlstate_failure(None), ReplicaFailure::Unavailable);
+        assert_eq!(sqlstate_failure(Some("57P03")), ReplicaFailure::Unavailable);
+        assert_eq!(sqlstate_failure(Some("53300")), ReplicaFailure::Unavailable);
+        assert_eq!(sqlstate_failure(Some("08006")), ReplicaFailure::Unavailable);
+        assert_eq!(sqlstate_failure(Some("40001")), ReplicaFailure::RecoveryConflict);
+        assert_eq!(sqlstate_failure(Some("40P01")), ReplicaFailure::RecoveryConflict);
+        assert_eq!(sqlstate_failure(Some("42P01")), ReplicaFailure::None);
+        assert_eq!(sqlstate_failure(Some("57014")), ReplicaFailure::None);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local postgres"]
+    async fn test_replica_failover() {
+        let unreachable = DatabaseConfig {
+            port: Some(1),
+            connect_timeout: Some(Duration::from_millis(100)),
+            ..database_test_config()
+        };
+        let config = ReplicationConfig {
+            health_check_interval: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let client = connect_to_replicated_database(
+            database_test_config(),
+            vec![database_test_config(), unreachable],
+            config,
+        )
+        .await
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let status = client.replica_status();
+        assert!(status[0].available);
+        assert!(!status[1].available);
+
+        let client = DbClient::from(client);
+        for _ in 0..4 {
+            let rows = client.execute(RecoveryReq).await.unwrap();
+            assert_eq!(rows.first(|x| x.in_recovery), Some(false));
+        }
+    }
+}
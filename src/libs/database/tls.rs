@@ -0,0 +1,229 @@
+use eyre::*;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::result::Result::Ok;
+use std::sync::Arc;
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+use crate::libs::listener::{load_certs, load_private_key};
+
+/// How the server certificate is verified, mirroring libpq's `sslmode`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TlsVerifyMode {
+    /// Verify the certificate chain and that the certificate matches the host (`verify-full`)
+    #[default]
+    Full,
+    /// Verify the certificate chain only (`verify-ca`)
+    Ca,
+    /// Encrypt without verifying the certificate (`require`), vulnerable to man-in-the-middle attacks
+    None,
+}
+
+/// TLS configuration of the connections to the database.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DatabaseTlsConfig {
+    /// PEM files of the CA certificates trusted to sign the server certificate
+    #[serde(default)]
+    pub ca_certs: Vec<PathBuf>,
+    /// PEM file of the client certificate chain, for client certificate authentication
+    pub client_cert: Option<PathBuf>,
+    /// PEM file of the PKCS#8 private key of the client certificate
+    pub client_key: Option<PathBuf>,
+    #[serde(default)]
+    pub verify: TlsVerifyMode,
+}
+
+/// Accepts certificates with a valid chain whatever host they are issued for.
+#[derive(Debug)]
+struct NoHostnameVerifier(Arc<WebPkiServerVerifier>);
+
+impl ServerCertVerifier for NoHostnameVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self
+            .0
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            // the name is checked after the chain, so the chain is valid
+            Err(rustls::Error::InvalidCertificate(CertificateError::NotValidForName)) => {
+                Ok(ServerCertVerified::assertion())
+            }
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
+
+/// Accepts any certificate, only checking the handshake signatures.
+#[derive(Debug)]
+struct NoVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+impl DatabaseTlsConfig {
+    /// Builds the rustls connector used by the pool and the listeners.
+    pub fn make_connector(&self) -> Result<MakeRustlsConnect> {
+        let provider = CryptoProvider::get_default()
+            .cloned()
+            .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()));
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&self.ca_certs)? {
+            roots.add(cert)?;
+        }
+        let verifier: Arc<dyn ServerCertVerifier> = match self.verify {
+            TlsVerifyMode::None => Arc::new(NoVerifier(provider.clone())),
+            mode => {
+                ensure!(!roots.is_empty(), "Database TLS verification requires ca_certs");
+                let verifier = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build()?;
+                match mode {
+                    TlsVerifyMode::Ca => Arc::new(NoHostnameVerifier(verifier)),
+                    _ => verifier,
+                }
+            }
+        };
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier);
+        let config = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                let certs = load_certs([cert])?;
+                let key = load_private_key(key)?
+                    .into_iter()
+                    .next()
+                    .with_context(|| format!("No PKCS#8 private key found in {}", key.display()))?;
+                builder.with_client_auth_cert(certs, PrivateKeyDer::Pkcs8(key))?
+            }
+            (None, None) => builder.with_no_client_auth(),
+            _ => bail!("Database TLS client_cert and client_key must be set together"),
+        };
+        Ok(MakeRustlsConnect::new(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::database::{connect_to_database, database_test_config, DatabaseConfig, DbClient, ToSql};
+    use deadpool_postgres::SslMode;
+
+    #[derive(Debug)]
+    struct SslReq;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, postgres_from_row::FromRow)]
+    struct SslRow {
+        ssl: bool,
+    }
+
+    impl crate::libs::database::DatabaseRequest for SslReq {
+        type ResponseRow = SslRow;
+        fn statement(&self) -> &str {
+            "SELECT ssl FROM pg_stat_ssl WHERE pid = pg_backend_pid()"
+        }
+        fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+            vec![]
+        }
+    }
+
+    async fn connect_tls(host: &str, verify: TlsVerifyMode) -> Result<bool> {
+        let config = DatabaseConfig {
+            host: Some(host.to_owned()),
+            ssl_mode: Some(SslMode::Require),
+            tls: Some(DatabaseTlsConfig {
+                ca_certs: vec![std::env::var("PG_TLS_CA")?.into()],
+                verify,
+                ..Default::default()
+            }),
+            ..database_test_config()
+        };
+        let client = DbClient::from(connect_to_database(config).await?);
+        let rows = client.execute(SslReq).await?;
+        rows.first(|x| x.ssl).context("no row")
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local postgres with TLS, signed by the CA in PG_TLS_CA"]
+    async fn test_tls_connection() {
+        assert!(connect_tls("localhost", TlsVerifyMode::Full).await.unwrap());
+        // the certificate is issued for localhost only
+        assert!(connect_tls("127.0.0.1", TlsVerifyMode::Full).await.is_err());
+        assert!(connect_tls("127.0.0.1", TlsVerifyMode::Ca).await.unwrap());
+        assert!(connect_tls("127.0.0.1", TlsVerifyMode::None).await.unwrap());
+    }
+
+    #[test]
+    fn test_make_connector() {
+        let config = DatabaseTlsConfig {
+            verify: TlsVerifyMode::None,
+            ..Default::default()
+        };
+        assert!(config.make_connector().is_ok());
+        let config = DatabaseTlsConfig::default();
+        assert!(config.make_connector().is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use deadpool_postgres::Object;
+use eyre::*;
+use futures::FutureExt;
+use postgres_from_row::FromRow;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::result::Result::Ok;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::*;
+
+use crate::libs::datatable::RDataTable;
+
+use super::{DatabaseRequest, MockCall, MockDbClient, PooledDbClient};
+
+/// The isolation level of a transaction, see the Postgres `SET TRANSACTION` documentation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    #[default]
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn as_sql(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadUncommitted => "READ UNCOMMITTED",
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+/// Holds the connection of a transaction. If it is dropped before the transaction finished
+/// (e.g. the future was cancelled), the connection is detached from the pool and closed, so
+/// the server rolls the transaction back instead of leaking it to the next user of the pool.
+struct TransactionConnection {
+    client: Option<Object>,
+    finished: bool,
+}
+
+impl Drop for TransactionConnection {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Some(client) = self.client.take() {
+                warn!("Transaction dropped before finishing, closing its connection");
+                drop(Object::take(client));
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+enum TransactionBackend {
+    Pooled(Arc<Mutex<TransactionConnection>>),
+    /// Answers with the responses of the mock and records the statements as calls
+    Mock(MockDbClient),
+}
+
+/// `DbTransaction` is a handle to an open transaction, passed to the closure of
+/// `DbClient::transaction`. Requests executed through it run on the transaction's connection.
+#[derive(Clone)]
+pub struct DbTransaction {
+    backend: TransactionBackend,
+    depth: u32,
+}
+
+impl DbTransaction {
+    /// Executes one or more statements separated by semicolons, without parameters.
+    pub async fn batch_execute(&self, sql: &str) -> Result<()> {
+        let conn = match &self.backend {
+            TransactionBackend::Pooled(conn) => conn.lock().await,
+            TransactionBackend::Mock(mock) => {
+                mock.record(MockCall::statement("batch_execute", sql));
+                return Ok(());
+            }
+        };
+        let client = conn.client.as_ref().context("Transaction connection is closed")?;
+        client.batch_execute(sql).await?;
+        Ok(())
+    }
+
+    async fn finish(&self) {
+        if let TransactionBackend::Pooled(conn) = &self.backend {
+            conn.lock().await.finished = true;
+        }
+    }
+
+    pub async fn execute<T: DatabaseRequest + Debug + 'static>(&self, req: T) -> Result<RDataTable<T::ResponseRow>> {
+        let conn = match &self.backend {
+            TransactionBackend::Pooled(conn) => conn.lock().await,
+            TransactionBackend::Mock(mock) => return mock.execute(req).await,
+        };
+        let client = conn.client.as_ref().context("Transaction connection is closed")?;
+        let statement = client.prepare_cached(req.statement()).await?;
+        let rows = client.query(&statement, &req.params()).await?;
+        debug!("Database query in transaction: {:?}", req);
+        let mut response = RDataTable::with_capacity(rows.len());
+        for row in rows {
+            response.push(T::ResponseRow::try_from_row(&row)?);
+        }
+        Ok(response)
+    }
+
+    /// Runs `f` in a nested transaction backed by a savepoint. Errors and panics roll back to the
+    /// savepoint and leave the outer transaction usable.
+    pub async fn transaction<R, F, Fut>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(DbTransaction) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let depth = self.depth + 1;
+        let savepoint = format!("sp_{}", depth);
+        let nested = DbTransaction {
+            backend: self.backend.clone(),
+            depth,
+        };
+        run_transaction(
+            nested,
+            format!("SAVEPOINT {}", savepoint),
+            format!("RELEASE SAVEPOINT {}", savepoint),
+            format!("ROLLBACK TO SAVEPOINT {}", savepoint),
+            f,
+        )
+        .await
+    }
+}
+
+async fn run_transaction<R, F, Fut>(
+    tx: DbTransaction,
+    begin: String,
+    commit: String,
+    rollback: String,
+    f: F,
+) -> Result<R>
+where
+    F: FnOnce(DbTransaction) -> Fut,
+    Fut: Future<Output = Result<R>>,
+{
+    tx.batch_execute(&begin).await?;
+    let result = AssertUnwindSafe(f(tx.clone())).catch_unwind().await;
+    let outermost = tx.depth == 0;
+    match result {
+        Ok(Ok(value)) => {
+            tx.batch_execute(&commit).await?;
+            if outermost {
+                tx.finish().await;
+            }
+            Ok(value)
+        }
+        Ok(Err(err)) => {
+            match tx.batch_execute(&rollback).await {
+                Ok(()) if outermost => tx.finish().await,
+                Ok(()) => {}
+                Err(rollback_err) => warn!("Failed to roll back transaction: {:?}", rollback_err),
+            }
+            Err(err)
+        }
+        Err(panic) => {
+            match tx.batch_execute(&rollback).await {
+                Ok(()) if outermost => tx.finish().await,
+                Ok(()) => {}
+                Err(rollback_err) => warn!("Failed to roll back transaction: {:?}", rollback_err),
+            }
+            std::panic::resume_unwind(panic)
+        }
+    }
+}
+
+impl PooledDbClient {
+    /// Runs `f` in a transaction on a dedicated pooled connection. The transaction is committed if
+    /// `f` returns `Ok` and rolled back if it returns `Err` or panics.
+    pub async fn transaction<R, F, Fut>(&self, isolation: IsolationLevel, f: F) -> Result<R>
+    where
+        F: FnOnce(DbTransaction) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let client = self.pool.get().await.context("Failed to connect to database")?;
+        let conn = TransactionConnection {
+            client: Some(client),
+            finished: false,
+        };
+        let tx = DbTransaction {
+            backend: TransactionBackend::Pooled(Arc::new(Mutex::new(conn))),
+            depth: 0,
+        };
+        run_transaction(
+            tx,
+            format!("BEGIN ISOLATION LEVEL {}", isolation.as_sql()),
+            "COMMIT".to_owned(),
+            "ROLLBACK".to_owned(),
+            f,
+        )
+        .await
+    }
+}
+
+impl MockDbClient {
+    /// Runs `f` in a mock transaction, recording `BEGIN`, then `COMMIT` or `ROLLBACK` as
+    /// `batch_execute` calls around the requests of `f`. Nothing is actually rolled back.
+    pub async fn transaction<R, F, Fut>(&self, isolation: IsolationLevel, f: F) -> Result<R>
+    where
+        F: FnOnce(DbTransaction) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let tx = DbTransaction {
+            backend: TransactionBackend::Mock(self.clone()),
+            depth: 0,
+        };
+        run_transaction(
+            tx,
+            format!("BEGIN ISOLATION LEVEL {}", isolation.as_sql()),
+            "COMMIT".to_owned(),
+            "ROLLBACK".to_owned(),
+            f,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::database::{connect_to_database, database_test_config, DbClient, ToSql};
+
+    #[derive(Debug)]
+    struct Exec(&'static str);
+
+    impl DatabaseRequest for Exec {
+        type ResponseRow = CountRow;
+        fn statement(&self) -> &str {
+            self.0
+        }
+        fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+            vec![]
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, postgres_from_row::FromRow)]
+    struct CountRow {
+        count: i64,
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local postgres"]
+    async fn test_transaction_rollback() {
+        let client: DbClient = connect_to_database(database_test_config()).await.unwrap().into();
+        let setup = "CREATE TABLE IF NOT EXISTS transaction_test (id bigint); TRUNCATE transaction_test";
+        client
+            .transaction(move |tx| async move { tx.batch_execute(setup).await })
+            .await
+            .unwrap();
+
+        let result: Result<()> = client
+            .transaction(|tx| async move {
+                tx.batch_execute("INSERT INTO transaction_test VALUES (1)").await?;
+                let nested: Result<()> = tx
+                    .transaction(|tx| async move {
+                        tx.batch_execute("INSERT INTO transaction_test VALUES (2)").await?;
+                        bail!("rollback to savepoint")
+                    })
+                    .await;
+                assert!(nested.is_err());
+                Ok(())
+            })
+            .await;
+        result.unwrap();
+        let result: Result<()> = client
+            .transaction(|tx| async move {
+                tx.batch_execute("INSERT INTO transaction_test VALUES (3)").await?;
+                bail!("rollback")
+            })
+            .await;
+        assert!(result.is_err());
+
+        let rows = client
+            .execute(Exec("SELECT count(*) AS count FROM transaction_test"))
+            .await
+            .unwrap();
+        assert_eq!(rows.first(|x| x.count), Some(1));
+    }
+}
@@ -1,7 +1,13 @@
 use std::future::Future;
+use std::io::Write;
 
-use eyre::Result;
+use convert_case::{Case, Casing};
+use eyre::{ContextCompat, Result};
+use serde::ser::SerializeMap;
 use serde::*;
+use serde_json::Value;
+
+use crate::model::Field;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RDataTable<T> {
@@ -16,6 +22,11 @@ impl<T> IntoIterator for RDataTable<T> {
         self.rows.into_iter()
     }
 }
+impl<T> From<Vec<T>> for RDataTable<T> {
+    fn from(rows: Vec<T>) -> Self {
+        Self { rows }
+    }
+}
 impl<T> RDataTable<T> {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
@@ -57,3 +68,288 @@ impl<T> RDataTable<T> {
         Ok(futures)
     }
 }
+
+/// `DataColumns` is the columnar form of a `RDataTable`, serialized as `{name: [values]}` in the
+/// order of the fields.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DataColumns {
+    pub columns: Vec<(String, Vec<Value>)>,
+}
+
+impl DataColumns {
+    pub fn get(&self, name: &str) -> Option<&Vec<Value>> {
+        self.columns.iter().find(|(x, _)| x == name).map(|(_, values)| values)
+    }
+}
+
+impl Serialize for DataColumns {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for (name, values) in &self.columns {
+            map.serialize_entry(name, values)?;
+        }
+        map.end()
+    }
+}
+
+/// Converts `row` to the values of `fields`. Fields are looked up by name, then by camelCase name
+/// to match the JSON convention of endpoint types. Missing fields are `null`.
+fn row_values(fields: &[Field], row: &impl Serialize) -> Result<Vec<Value>> {
+    let row = serde_json::to_value(row)?;
+    let object = row.as_object().context("RDataTable rows must serialize to objects")?;
+    Ok(fields
+        .iter()
+        .map(|field| {
+            object
+                .get(&field.name)
+                .or_else(|| object.get(&field.name.to_case(Case::Camel)))
+                .cloned()
+                .unwrap_or(Value::Null)
+        })
+        .collect())
+}
+
+/// The name of the column of `field`, its camelCase key in the JSON rows.
+fn column_name(field: &Field) -> String {
+    field.name.to_case(Case::Camel)
+}
+
+fn write_csv_record(writer: &mut impl Write, values: impl IntoIterator<Item = String>) -> std::io::Result<()> {
+    for (i, value) in values.into_iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        if value.contains([',', '"', '\n', '\r']) {
+            write!(writer, "\"{}\"", value.replace('"', "\"\""))?;
+        } else {
+            writer.write_all(value.as_bytes())?;
+        }
+    }
+    writer.write_all(b"\r\n")
+}
+
+fn csv_value(value: &Value) -> String {
+    match value {
+        Value::Null => "".to_owned(),
+        Value::String(x) => x.clone(),
+        x => x.to_string(),
+    }
+}
+
+impl<T: Serialize> RDataTable<T> {
+    /// Converts the rows to columns named after the camelCase names of `fields`, usually the
+    /// fields of the `Type::DataTable` of the rows.
+    pub fn to_columns(&self, fields: &[Field]) -> Result<DataColumns> {
+        let mut columns: Vec<(String, Vec<Value>)> = fields
+            .iter()
+            .map(|x| (column_name(x), Vec::with_capacity(self.rows.len())))
+            .collect();
+        for row in &self.rows {
+            for (column, value) in columns.iter_mut().zip(row_values(fields, row)?) {
+                column.1.push(value);
+            }
+        }
+        Ok(DataColumns { columns })
+    }
+
+    /// Writes the rows as CSV (RFC 4180) with a header of the camelCase names of `fields`. Nested
+    /// values are written as JSON and `null` as an empty cell.
+    pub fn write_csv(&self, fields: &[Field], mut writer: impl Write) -> Result<()> {
+        write_csv_record(&mut writer, fields.iter().map(column_name))?;
+        for row in &self.rows {
+            let values = row_values(fields, row)?;
+            write_csv_record(&mut writer, values.iter().map(csv_value))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod arrow {
+    use std::io::Write;
+    use std::sync::Arc;
+
+    use arrow_array::builder::{BooleanBuilder, Float64Builder, Int32Builder, Int64Builder, StringBuilder};
+    use arrow_array::{ArrayRef, RecordBatch};
+    use arrow_schema::{DataType, Field as ArrowField, Schema};
+    use eyre::{bail, Result};
+    use parquet::arrow::ArrowWriter;
+    use serde::Serialize;
+    use serde_json::Value;
+
+    use super::{column_name, csv_value, RDataTable};
+    use crate::model::{Field, Type};
+
+    /// The arrow type of a `model::Type`, strings for the types without an arrow equivalent.
+    fn data_type(ty: &Type) -> DataType {
+        match ty {
+            Type::Optional(ty) => data_type(ty),
+            Type::Int => DataType::Int32,
+            Type::BigInt | Type::TimeStampMs => DataType::Int64,
+            Type::Numeric => DataType::Float64,
+            Type::Boolean => DataType::Boolean,
+            _ => DataType::Utf8,
+        }
+    }
+
+    fn build_array(field: &Field, values: &[Value]) -> Result<ArrayRef> {
+        let mismatch = |value: &Value| eyre::eyre!("Invalid value for column {}: {}", field.name, value);
+        let array: ArrayRef = match data_type(&field.ty) {
+            DataType::Int32 => {
+                let mut builder = Int32Builder::with_capacity(values.len());
+                for value in values {
+                    match value {
+                        Value::Null => builder.append_null(),
+                        x => {
+                            let value = x.as_i64().and_then(|x| x.try_into().ok());
+                            builder.append_value(value.ok_or_else(|| mismatch(x))?)
+                        }
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            DataType::Int64 => {
+                let mut builder = Int64Builder::with_capacity(values.len());
+                for value in values {
+                    match value {
+                        Value::Null => builder.append_null(),
+                        x => builder.append_value(x.as_i64().ok_or_else(|| mismatch(x))?),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            DataType::Float64 => {
+                let mut builder = Float64Builder::with_capacity(values.len());
+                for value in values {
+                    match value {
+                        Value::Null => builder.append_null(),
+                        // decimals may be serialized as strings
+                        Value::String(x) => builder.append_value(x.parse().map_err(|_| mismatch(value))?),
+                        x => builder.append_value(x.as_f64().ok_or_else(|| mismatch(x))?),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            DataType::Boolean => {
+                let mut builder = BooleanBuilder::with_capacity(values.len());
+                for value in values {
+                    match value {
+                        Value::Null => builder.append_null(),
+                        x => builder.append_value(x.as_bool().ok_or_else(|| mismatch(x))?),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            DataType::Utf8 => {
+                let mut builder = StringBuilder::new();
+                for value in values {
+                    match value {
+                        Value::Null => builder.append_null(),
+                        x => builder.append_value(csv_value(x)),
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            ty => bail!("Unsupported arrow type {}", ty),
+        };
+        Ok(array)
+    }
+
+    impl<T: Serialize> RDataTable<T> {
+        /// Converts the rows to an arrow `RecordBatch` with a column per field, see
+        /// [`RDataTable::to_columns`].
+        pub fn to_record_batch(&self, fields: &[Field]) -> Result<RecordBatch> {
+            let schema = Schema::new(
+                fields
+                    .iter()
+                    .map(|x| ArrowField::new(column_name(x), data_type(&x.ty), true))
+                    .collect::<Vec<_>>(),
+            );
+            let columns = self.to_columns(fields)?;
+            let arrays = fields
+                .iter()
+                .zip(&columns.columns)
+                .map(|(field, (_, values))| build_array(field, values))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(RecordBatch::try_new(Arc::new(schema), arrays)?)
+        }
+
+        /// Writes the rows as a Parquet file, see [`RDataTable::to_record_batch`].
+        pub fn write_parquet(&self, fields: &[Field], writer: impl Write + Send) -> Result<()> {
+            let batch = self.to_record_batch(fields)?;
+            let mut writer = ArrowWriter::try_new(writer, batch.schema(), None)?;
+            writer.write(&batch)?;
+            writer.close()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Type;
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(rename_all = "camelCase")]
+    struct TradeRow {
+        trade_id: i64,
+        symbol: String,
+        price: Option<f64>,
+    }
+
+    fn trades() -> (RDataTable<TradeRow>, Vec<Field>) {
+        let rows = vec![
+            TradeRow {
+                trade_id: 1,
+                symbol: "BTC,USD".to_owned(),
+                price: Some(1.5),
+            },
+            TradeRow {
+                trade_id: 2,
+                symbol: "ETH".to_owned(),
+                price: None,
+            },
+        ];
+        let fields = vec![
+            Field::new("trade_id", Type::BigInt),
+            Field::new("symbol", Type::String),
+            Field::new("price", Type::optional(Type::Numeric)),
+        ];
+        (rows.into(), fields)
+    }
+
+    #[test]
+    fn test_to_columns() {
+        let (rows, fields) = trades();
+        let columns = rows.to_columns(&fields).unwrap();
+        assert_eq!(
+            serde_json::to_string(&columns).unwrap(),
+            r#"{"tradeId":[1,2],"symbol":["BTC,USD","ETH"],"price":[1.5,null]}"#
+        );
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let (rows, fields) = trades();
+        let mut csv = vec![];
+        rows.write_csv(&fields, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "tradeId,symbol,price\r\n1,\"BTC,USD\",1.5\r\n2,ETH,\r\n"
+        );
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_write_parquet() {
+        let (rows, fields) = trades();
+        let batch = rows.to_record_batch(&fields).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 3);
+        let mut parquet = vec![];
+        rows.write_parquet(&fields, &mut parquet).unwrap();
+        assert_eq!(&parquet[..4], b"PAR1");
+    }
+}
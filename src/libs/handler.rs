@@ -22,24 +22,21 @@ pub trait RequestHandlerErased: Send + Sync {
 #[async_trait(?Send)]
 impl<T: RequestHandler> RequestHandlerErased for T {
     async fn handle(&self, toolbox: &ArcToolbox, ctx: RequestContext, req: Value) {
-        // TODO: find a better way to avoid double parsing or serialization
-        let buf = serde_json::to_string(&req).unwrap();
-        let data: T::Request = match serde_json::from_value(req) {
+        let data: T::Request = match serde_path_to_error::deserialize(&req) {
             Ok(data) => data,
             Err(err) => {
-                let jd = &mut serde_json::Deserializer::from_str(&buf);
-                let data: Result<T::Request, _> = serde_path_to_error::deserialize(jd);
-                let path = data.err().map(|err| err.path().to_string());
+                let path = err.path().to_string();
+                let message = if path == "." {
+                    err.inner().to_string()
+                } else {
+                    format!("{}: {}", path, err.inner())
+                };
                 toolbox.send(
                     ctx.connection_id,
                     request_error_to_resp(
                         &ctx,
                         ErrorCode::new(100400), // Bad Request
-                        if let Some(path) = path {
-                            format!("{}: {}", path, err)
-                        } else {
-                            format!("{}", err)
-                        },
+                        message,
                     ),
                 );
                 return;
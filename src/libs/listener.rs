@@ -19,8 +19,8 @@ pub trait ConnectionListener: Send + Sync + Unpin {
     type Channel1: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static;
     type Channel2: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static;
 
-    fn accept(&self) -> BoxFuture<Result<(Self::Channel1, SocketAddr)>>;
-    fn handshake(&self, channel: Self::Channel1) -> BoxFuture<Result<Self::Channel2>>;
+    fn accept(&self) -> BoxFuture<'_, Result<(Self::Channel1, SocketAddr)>>;
+    fn handshake(&self, channel: Self::Channel1) -> BoxFuture<'_, Result<Self::Channel2>>;
 }
 
 pub struct TcpListener {
@@ -36,14 +36,14 @@ impl ConnectionListener for TcpListener {
     type Channel1 = TcpStream;
     type Channel2 = TcpStream;
 
-    fn accept(&self) -> BoxFuture<Result<(Self::Channel1, SocketAddr)>> {
+    fn accept(&self) -> BoxFuture<'_, Result<(Self::Channel1, SocketAddr)>> {
         async {
             let (stream, addr) = self.listener.accept().await?;
             Ok((stream, addr))
         }
         .boxed()
     }
-    fn handshake(&self, channel: Self::Channel1) -> BoxFuture<Result<Self::Channel2>> {
+    fn handshake(&self, channel: Self::Channel1) -> BoxFuture<'_, Result<Self::Channel2>> {
         async move { Ok(channel) }.boxed()
     }
 }
@@ -78,10 +78,10 @@ impl<T: ConnectionListener> TlsListener<T> {
 impl<T: ConnectionListener + 'static> ConnectionListener for TlsListener<T> {
     type Channel1 = T::Channel1;
     type Channel2 = TlsStream<T::Channel2>;
-    fn accept(&self) -> BoxFuture<Result<(Self::Channel1, SocketAddr)>> {
+    fn accept(&self) -> BoxFuture<'_, Result<(Self::Channel1, SocketAddr)>> {
         self.tcp.accept()
     }
-    fn handshake(&self, channel: Self::Channel1) -> BoxFuture<Result<Self::Channel2>> {
+    fn handshake(&self, channel: Self::Channel1) -> BoxFuture<'_, Result<Self::Channel2>> {
         async {
             let channel = self.tcp.handshake(channel).await?;
             let tls_stream = self.acceptor.accept(channel).await?;
@@ -101,11 +101,8 @@ pub fn load_certs<'a, T: AsRef<Path>>(path: impl IntoIterator<Item = T>) -> Resu
         let reader = &mut std::io::BufReader::new(f);
         let certs_results = certs(reader);
 
-        let certs: Vec<CertificateDer> = certs_results.filter_map(|result| match result {
-            Ok(value) => Some(value),  // Map Ok values to Some(value)
-            Err(_) => None,            // Ignore Err values
-        })
-        .collect();
+        // Ignore Err values
+        let certs: Vec<CertificateDer> = certs_results.filter_map(|result| result.ok()).collect();
 
         r_certs.extend(certs);
     }
@@ -116,11 +113,10 @@ pub fn load_certs<'a, T: AsRef<Path>>(path: impl IntoIterator<Item = T>) -> Resu
 pub fn load_private_key(path: &PathBuf) -> Result<Vec<rustls::pki_types::PrivatePkcs8KeyDer<'static>>> {
     let file = std::fs::File::open(path).with_context(|| format!("Failed to open private key {:?} ", path))?;
     let mut reader = std::io::BufReader::new(file);
-    let keys = rustls_pemfile::pkcs8_private_keys(&mut reader).into_iter().filter_map(|result| match result {
-        Ok(value) => Some(value),  // Map Ok values to Some(value)
-        Err(_) => None,            // Ignore Err values
-    })
-    .collect();
+    // Ignore Err values
+    let keys = rustls_pemfile::pkcs8_private_keys(&mut reader)
+        .filter_map(|result| result.ok())
+        .collect();
 
     Ok(keys)
 }
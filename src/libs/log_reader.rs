@@ -52,11 +52,11 @@ impl FromStr for LogEntry {
 pub async fn get_log_entries(path: impl AsRef<std::path::Path>, limit: usize) -> eyre::Result<Vec<LogEntry>> {
     // Specify the path to your log file
     let file = std::fs::File::open(path.as_ref())?;
-    let mut lines = RevLines::new(file);
+    let lines = RevLines::new(file);
     // get all entries first
     let mut entries = tokio::task::spawn_blocking(move || {
         let mut entries = vec![];
-        while let Some(line) = lines.next() {
+        for line in lines {
             if entries.len() >= limit {
                 break;
             }
@@ -130,11 +130,14 @@ mod tests {
         assert_eq!(entry.message, "terminated diff 0_table_limit_1 initially");
     }
     #[tokio::test]
-    // #[allow(dead_code)]
     async fn log_entry_from_file() {
-        // NOTE use some valid file when needed
-        let path = "/Users/jack/Dev/InsolventCapital/trading.insolvent.app-backend/log/user.log";
-        let entries = get_log_entries(path, 20).await.unwrap();
+        let path = std::env::temp_dir().join(format!("log_entry_from_file_{}.log", std::process::id()));
+        let lines: Vec<String> = (0..30)
+            .map(|i| format!("2024-05-18T14:26:36.709390Z  WARN                 main trading_be: {}: line {}", i, i))
+            .collect();
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let entries = get_log_entries(&path, 20).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
         println!("{:?}", entries[0]);
         assert_eq!(entries.len(), 20);
     }
@@ -0,0 +1,169 @@
+use eyre::*;
+use serde::de::DeserializeOwned;
+use serde::*;
+
+use super::datatable::RDataTable;
+use super::error_code::ErrorCode;
+use super::toolbox::CustomError;
+use super::{DEFAULT_LIMIT, DEFAULT_OFFSET};
+
+/// The largest page size accepted by [`Pagination::validate`] by default.
+pub const MAX_LIMIT: i32 = 1000;
+
+/// `Pagination` is the page requested by a paginated endpoint, either by offset or after a
+/// cursor returned in [`Page::next_cursor`]. Missing fields default to `DEFAULT_LIMIT` and
+/// `DEFAULT_OFFSET`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Pagination {
+    Keyset {
+        #[serde(default)]
+        limit: Option<i32>,
+        cursor: String,
+    },
+    Offset {
+        #[serde(default)]
+        limit: Option<i32>,
+        #[serde(default)]
+        offset: Option<i32>,
+    },
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self::Offset {
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
+impl Pagination {
+    /// The number of rows requested.
+    pub fn limit(&self) -> i32 {
+        match self {
+            Self::Keyset { limit, .. } | Self::Offset { limit, .. } => limit.unwrap_or(DEFAULT_LIMIT),
+        }
+    }
+
+    /// The number of rows skipped, always `DEFAULT_OFFSET` for keyset pagination.
+    pub fn offset(&self) -> i32 {
+        match self {
+            Self::Keyset { .. } => DEFAULT_OFFSET,
+            Self::Offset { offset, .. } => offset.unwrap_or(DEFAULT_OFFSET),
+        }
+    }
+
+    /// The cursor the page starts after, if any.
+    pub fn cursor(&self) -> Option<&str> {
+        match self {
+            Self::Keyset { cursor, .. } => Some(cursor),
+            Self::Offset { .. } => None,
+        }
+    }
+
+    /// Decodes the cursor into the key of the last row of the previous page, see [`encode_cursor`].
+    pub fn cursor_key<K: DeserializeOwned>(&self) -> Result<Option<K>> {
+        self.cursor().map(decode_cursor).transpose()
+    }
+
+    /// Fails with a BadRequest error unless the limit is in `1..=max_limit` and the offset and
+    /// cursor are valid.
+    pub fn validate(&self, max_limit: i32) -> Result<()> {
+        let bad_request = |reason: String| CustomError::new(ErrorCode::new(100400), reason);
+        let limit = self.limit();
+        if limit < 1 || limit > max_limit {
+            bail!(bad_request(format!("limit must be between 1 and {}, got {}", max_limit, limit)));
+        }
+        if self.offset() < 0 {
+            bail!(bad_request(format!("offset must not be negative, got {}", self.offset())));
+        }
+        if let Some(cursor) = self.cursor() {
+            if hex::decode(cursor).is_err() {
+                bail!(bad_request("invalid cursor".to_owned()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Encodes the key of a row (e.g. `(created_at, id)`) into an opaque cursor.
+pub fn encode_cursor<K: Serialize>(key: &K) -> Result<String> {
+    Ok(hex::encode(serde_json::to_vec(key)?))
+}
+
+/// Decodes a cursor created by [`encode_cursor`].
+pub fn decode_cursor<K: DeserializeOwned>(cursor: &str) -> Result<K> {
+    let bytes = hex::decode(cursor).context("Invalid cursor")?;
+    serde_json::from_slice(&bytes).context("Invalid cursor")
+}
+
+/// `Page` is the response of a paginated endpoint, declared with `Type::page`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    #[serde(flatten)]
+    pub rows: RDataTable<T>,
+    /// The total number of rows, if counted
+    pub total: Option<i64>,
+    /// The cursor of the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Creates a page of offset pagination.
+    pub fn offset(rows: RDataTable<T>, total: Option<i64>) -> Self {
+        Self {
+            rows,
+            total,
+            next_cursor: None,
+        }
+    }
+
+    /// Creates a page of keyset pagination. If the page is full, `next_cursor` is built from the
+    /// key of its last row.
+    pub fn keyset<K: Serialize>(
+        rows: RDataTable<T>,
+        pagination: &Pagination,
+        total: Option<i64>,
+        key: impl Fn(&T) -> K,
+    ) -> Result<Self> {
+        let next_cursor = match rows.rows().last() {
+            Some(last) if rows.len() >= pagination.limit() as usize => Some(encode_cursor(&key(last))?),
+            _ => None,
+        };
+        Ok(Self {
+            rows,
+            total,
+            next_cursor,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pagination() {
+        let pagination: Pagination = serde_json::from_str("{}").unwrap();
+        assert_eq!(pagination.limit(), DEFAULT_LIMIT);
+        assert_eq!(pagination.offset(), DEFAULT_OFFSET);
+        assert!(pagination.validate(MAX_LIMIT).is_ok());
+
+        let pagination: Pagination = serde_json::from_str(r#"{"limit": 2000, "offset": 10}"#).unwrap();
+        assert_eq!(pagination.offset(), 10);
+        assert!(pagination.validate(MAX_LIMIT).is_err());
+
+        let rows = RDataTable::from(vec![(1, "a"), (2, "b")]);
+        let page = Page::keyset(rows, &Pagination::Offset { limit: Some(2), offset: None }, None, |x| x.0).unwrap();
+        let cursor = page.next_cursor.clone().unwrap();
+        let pagination: Pagination = serde_json::from_value(serde_json::json!({ "cursor": cursor })).unwrap();
+        assert!(pagination.validate(MAX_LIMIT).is_ok());
+        assert_eq!(pagination.cursor_key::<i32>().unwrap(), Some(2));
+
+        let value = serde_json::to_value(&page).unwrap();
+        assert_eq!(value["rows"][1][1], "b");
+        assert_eq!(value["nextCursor"], cursor);
+    }
+}
@@ -1,5 +1,6 @@
 use dashmap::DashMap;
 use eyre::{Context, Result};
+use futures::{Stream, TryStreamExt};
 use parking_lot::RwLock;
 use serde::*;
 use serde_json::Value;
@@ -7,12 +8,15 @@ use std::fmt::{Debug, Display, Formatter};
 use std::net::{IpAddr, Ipv4Addr};
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::tungstenite::Message;
 use tracing::*;
 
 use super::error_code::ErrorCode;
 use super::log::LogLevel;
-use super::ws::{internal_error_to_resp, request_error_to_resp, ConnectionId, WsConnection, WsLogResponse, WsResponseValue, WsStreamState, WsSuccessResponse};
+use super::ws::{internal_error_to_resp, request_error_to_resp, ConnectionId, WsConnection, WsLogResponse, WsResponseValue, WsStreamResponse, WsStreamState, WsSuccessResponse};
+use super::ws::{WebsocketStates, WsConnectionInfo};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NoResponseError;
@@ -95,18 +99,25 @@ impl RequestContext {
     }
 }
 
+type WsStates = Arc<DashMap<ConnectionId, Arc<WsStreamState>>>;
+
 pub struct Toolbox {
     pub send_msg: RwLock<Arc<dyn Fn(ConnectionId, WsResponseValue) -> bool + Send + Sync>>,
+    ws_states: RwLock<Option<(WsStates, bool)>>,
+    ws_registry: RwLock<Option<Arc<WebsocketStates>>>,
 }
 pub type ArcToolbox = Arc<Toolbox>;
 impl Toolbox {
     pub fn new() -> Arc<Self> {
         Arc::new(Self {
             send_msg: RwLock::new(Arc::new(|_conn_id, _msg| false)),
+            ws_states: RwLock::new(None),
+            ws_registry: RwLock::new(None),
         })
     }
 
-    pub fn set_ws_states(&self, states: Arc<DashMap<ConnectionId, Arc<WsStreamState>>>, oneshot: bool) {
+    pub fn set_ws_states(&self, states: WsStates, oneshot: bool) {
+        *self.ws_states.write() = Some((states.clone(), oneshot));
         *self.send_msg.write() = Arc::new(move |conn_id, msg| {
             let state = if let Some(state) = states.get(&conn_id) {
                 state
@@ -118,6 +129,72 @@ impl Toolbox {
         });
     }
 
+    /// Like `set_ws_states`, and enables the per-user and per-role operations of the registry.
+    pub fn set_ws_registry(&self, registry: Arc<WebsocketStates>, oneshot: bool) {
+        self.set_ws_states(registry.clone_states(), oneshot);
+        *self.ws_registry.write() = Some(registry);
+    }
+    /// The registry of live connections, set when the websocket server starts listening.
+    pub fn ws_registry(&self) -> Option<Arc<WebsocketStates>> {
+        self.ws_registry.read().clone()
+    }
+    /// Lists the live connections with their address, connect time and bytes sent.
+    pub fn list_connections(&self) -> Vec<WsConnectionInfo> {
+        self.ws_registry().map(|x| x.list()).unwrap_or_default()
+    }
+    /// Sends `resp` to every connection of `user_id`, returns the number of connections sent to.
+    pub fn send_to_user(&self, user_id: i64, resp: &WsResponseValue) -> usize {
+        let Some(registry) = self.ws_registry() else {
+            return 0;
+        };
+        self.send_to_states(registry.get_user_states(user_id), resp)
+    }
+    /// Sends `resp` to every connection with `role`, returns the number of connections sent to.
+    pub fn broadcast_to_role(&self, role: u32, resp: &WsResponseValue) -> usize {
+        let Some(registry) = self.ws_registry() else {
+            return 0;
+        };
+        self.send_to_states(registry.get_role_states(role), resp)
+    }
+    fn send_to_states(&self, states: Vec<Arc<WsStreamState>>, resp: &WsResponseValue) -> usize {
+        states
+            .iter()
+            .filter(|x| self.send(x.conn.connection_id, resp.clone()))
+            .count()
+    }
+    /// Closes the connection with a close frame carrying `reason`, the socket is dropped once the
+    /// frame is sent. Returns false if the connection is not live.
+    pub fn disconnect(&self, conn_id: ConnectionId, reason: &str) -> bool {
+        let Some(state) = self.ws_registry().and_then(|x| x.get_state(conn_id)) else {
+            return false;
+        };
+        // control frames are limited to 125 bytes, 2 of which are the close code
+        let mut len = reason.len().min(123);
+        while !reason.is_char_boundary(len) {
+            len -= 1;
+        }
+        let frame = CloseFrame {
+            code: CloseCode::Policy,
+            reason: reason[..len].to_owned().into(),
+        };
+        if let Err(err) = state.message_queue.try_send(Message::Close(Some(frame))) {
+            warn!("Failed to disconnect connection {}: {:?}", conn_id, err);
+            return false;
+        }
+        true
+    }
+    /// Closes every connection of `user_id`, returns the number of connections closed.
+    pub fn disconnect_user(&self, user_id: i64, reason: &str) -> usize {
+        let Some(registry) = self.ws_registry() else {
+            return 0;
+        };
+        registry
+            .get_user_states(user_id)
+            .iter()
+            .filter(|x| self.disconnect(x.conn.connection_id, reason))
+            .count()
+    }
+
     pub fn send_ws_msg(sender: &tokio::sync::mpsc::Sender<Message>, resp: WsResponseValue, oneshot: bool) {
         let resp = serde_json::to_string(&resp).unwrap();
         if let Err(err) = sender.try_send(resp.into()) {
@@ -130,6 +207,62 @@ impl Toolbox {
     pub fn send(&self, conn_id: ConnectionId, resp: WsResponseValue) -> bool {
         self.send_msg.read()(conn_id, resp)
     }
+    /// Like `send`, but waits for room in the connection's message queue instead of dropping the
+    /// message when it is full. Unlike `send`, it doesn't close oneshot connections, so that more
+    /// messages can follow.
+    pub async fn send_wait(&self, conn_id: ConnectionId, resp: WsResponseValue) -> bool {
+        let state = self.ws_states.read().clone();
+        let Some((states, _)) = state else {
+            return self.send(conn_id, resp);
+        };
+        let Some(sender) = states.get(&conn_id).map(|x| x.message_queue.clone()) else {
+            return false;
+        };
+        let resp = serde_json::to_string(&resp).unwrap();
+        sender.send(resp.into()).await.is_ok()
+    }
+    /// Queues a close frame after the pending messages if the connection is oneshot.
+    async fn close_oneshot(&self, conn_id: ConnectionId) {
+        let state = self.ws_states.read().clone();
+        let Some((states, true)) = state else {
+            return;
+        };
+        let Some(sender) = states.get(&conn_id).map(|x| x.message_queue.clone()) else {
+            return;
+        };
+        let _ = sender.send(Message::Close(None)).await;
+    }
+    /// Sends the items of `stream` to the requester as stream responses with `stream_code`, in
+    /// batches of up to `batch_size` items, e.g. the rows of `DbClient::execute_stream`.
+    ///
+    /// Waits for the connection to keep up instead of buffering the whole stream. Stops early if
+    /// the connection is closed, and returns the number of stream responses sent. Oneshot
+    /// connections are closed after the last batch.
+    pub async fn send_stream<T: Serialize>(
+        &self,
+        ctx: &RequestContext,
+        stream_code: u32,
+        stream: impl Stream<Item = Result<T>>,
+        batch_size: usize,
+    ) -> Result<u32> {
+        let mut stream = std::pin::pin!(stream.try_chunks(batch_size.max(1)));
+        let mut stream_seq = 0;
+        while let Some(batch) = stream.try_next().await.map_err(|x| x.1)? {
+            let resp = WsResponseValue::Stream(WsStreamResponse {
+                original_seq: ctx.seq,
+                method: ctx.method,
+                stream_seq,
+                stream_code,
+                data: serde_json::to_value(&batch)?,
+            });
+            if !self.send_wait(ctx.connection_id, resp).await {
+                return Ok(stream_seq);
+            }
+            stream_seq += 1;
+        }
+        self.close_oneshot(ctx.connection_id).await;
+        Ok(stream_seq)
+    }
     pub fn send_response(&self, ctx: &RequestContext, resp: impl Serialize) {
         self.send(
             ctx.connection_id,
@@ -3,8 +3,11 @@ use std::ops::{Deref, DerefMut};
 
 #[doc(hidden)]
 pub use alloy::primitives::{Address, B256 as H256, U256};
+use bytes::BytesMut;
+use postgres_types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+pub use uuid::Uuid;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct BlockchainAddress(pub Address);
@@ -146,3 +149,34 @@ impl DerefMut for BlockchainTransactionHash {
         &mut self.0
     }
 }
+
+/// Stores the value as its `0x` prefixed hex string, e.g. in a `varchar(42)` column.
+macro_rules! impl_sql_as_hex {
+    ($($t: ty),+) => {
+        $(
+            impl ToSql for $t {
+                fn to_sql(
+                    &self,
+                    ty: &Type,
+                    out: &mut BytesMut,
+                ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+                    format!("{:?}", self.0).to_sql(ty, out)
+                }
+                fn accepts(ty: &Type) -> bool {
+                    <String as ToSql>::accepts(ty)
+                }
+                to_sql_checked!();
+            }
+            impl<'a> FromSql<'a> for $t {
+                fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+                    Ok(Self(<&str as FromSql>::from_sql(ty, raw)?.parse()?))
+                }
+                fn accepts(ty: &Type) -> bool {
+                    <&str as FromSql>::accepts(ty)
+                }
+            }
+        )+
+    };
+}
+
+impl_sql_as_hex!(BlockchainAddress, BlockchainTransactionHash);
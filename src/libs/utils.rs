@@ -61,13 +61,13 @@ pub fn decimal_sf(num: rust_decimal::Decimal, sig_figs: usize) -> rust_decimal::
 /// ```
 /// let a = 123.456789;
 /// let b = 78.9;
-/// let aligned_a = lib::utils::align_precision(a, b);
+/// let aligned_a = endpoint_libs::libs::utils::align_precision(a, b);
 /// assert_eq!(aligned_a, 123.5);
 /// ```
 /// ```
 /// let a = 2.37;
 /// let b = 631.3;
-/// let aligned_a = lib::utils::align_precision(a, b);
+/// let aligned_a = endpoint_libs::libs::utils::align_precision(a, b);
 /// assert_eq!(aligned_a, 2.4);
 /// ```
 pub fn align_precision(a: f64, b: f64) -> f64 {
@@ -78,10 +78,10 @@ pub fn align_precision(a: f64, b: f64) -> f64 {
 }
 
 /// ```
-/// assert_eq!(lib::utils::count_dp(1.5), 1);
+/// assert_eq!(endpoint_libs::libs::utils::count_dp(1.5), 1);
 /// ```
 /// ```
-/// assert_eq!(lib::utils::count_dp(1.1), 1);
+/// assert_eq!(endpoint_libs::libs::utils::count_dp(1.1), 1);
 /// ```
 pub fn count_dp(num: f64) -> usize {
     // Convert the f64 to a string representation
@@ -1,16 +1,25 @@
 mod basics;
 mod client;
+mod client_error;
 mod conn;
 mod headers;
 mod push;
+mod reconnect;
 mod server;
 mod session;
 mod subs;
+mod subscription;
+mod validation;
 
 pub use basics::*;
 pub use client::*;
+pub use client_error::*;
 pub use conn::*;
 pub use headers::*;
+pub use push::*;
+pub use reconnect::*;
 pub use server::*;
 pub use session::*;
 pub use subs::*;
+pub use subscription::*;
+pub use validation::*;
@@ -2,7 +2,7 @@ use serde::*;
 use serde_json::Value;
 use std::fmt::Debug;
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicI64, AtomicU32};
+use std::sync::atomic::{AtomicI64, AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 
 use crate::libs::error_code::ErrorCode;
@@ -36,10 +36,17 @@ pub struct WsConnection {
     pub role: AtomicU32,
     pub address: SocketAddr,
     pub log_id: u64,
+    /// Unix timestamp in milliseconds of the handshake
+    pub connected_at: i64,
+    /// Bytes of the messages sent to the client so far
+    pub bytes_sent: AtomicU64,
 }
 impl WsConnection {
     pub fn get_user_id(&self) -> i64 {
-        self.user_id.load(std::sync::atomic::Ordering::Relaxed)
+        self.user_id.load(Ordering::Relaxed)
+    }
+    pub fn get_role(&self) -> u32 {
+        self.role.load(Ordering::Relaxed)
     }
 }
 
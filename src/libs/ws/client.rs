@@ -1,117 +1,530 @@
-use eyre::{bail, eyre, Context, Result};
+use eyre::{Context, Result};
 use futures::SinkExt;
 use futures::StreamExt;
+use parking_lot::Mutex;
 use reqwest::header::HeaderValue;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Interval;
 use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use tokio_tungstenite::tungstenite::Message;
 use tokio_tungstenite::MaybeTlsStream;
 use tokio_tungstenite::WebSocketStream;
+use tokio_util::sync::CancellationToken;
 use tracing::*;
 
 use crate::libs::log::LogLevel;
 use crate::libs::ws::WsLogResponse;
 use crate::libs::ws::WsRequestGeneric;
 use crate::libs::ws::WsResponseGeneric;
+use crate::model::EndpointSchema;
 
-use super::WsResponseValue;
+use super::{WsClientError, WsClientResult, WsResponseValue, WsStreamResponse};
 
 pub trait WsRequest: Serialize + DeserializeOwned + Send + Sync + Clone {
     type Response: WsResponse;
     const METHOD_ID: u32;
+    /// The endpoint schema as JSON, empty for `#[derive(Endpoint)]` endpoints whose schema is only
+    /// known at runtime.
     const SCHEMA: &'static str;
+
+    /// Returns the schema of the endpoint, parsed from `SCHEMA` unless overridden
+    /// (e.g. by `#[derive(Endpoint)]`).
+    fn schema() -> EndpointSchema {
+        serde_json::from_str(Self::SCHEMA)
+            .unwrap_or_else(|err| panic!("Invalid SCHEMA of {}: {}", std::any::type_name::<Self>(), err))
+    }
 }
 pub trait WsResponse: Serialize + DeserializeOwned + Send + Sync + Clone {
     type Request: WsRequest;
 }
+
+/// `WsStreamRequest` is a request whose endpoint streams messages of type `Stream` after its
+/// response, see [`WsClient::subscribe`].
+pub trait WsStreamRequest: WsRequest {
+    type Stream: DeserializeOwned + Send;
+
+    /// Returns the method and params of the request that stops the stream on the server, sent
+    /// when the subscription is dropped. By default the stream is only dropped locally.
+    fn unsubscribe_request(&self) -> Option<(u32, Value)> {
+        None
+    }
+}
+
+/// Receives the stream messages routed to a request or a stream code, see [`WsClient::request_with_stream`].
+pub type WsStreamReceiver = mpsc::UnboundedReceiver<WsStreamResponse>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WsClientConfig {
+    /// How often a ping is sent to check the connection, no pings if `None`
+    pub heartbeat_interval: Option<Duration>,
+    /// The connection is considered dead if nothing is received for `heartbeat_interval` plus this
+    pub heartbeat_timeout: Duration,
+    /// How long requests wait for their response by default, see [`WsClient::with_timeout`]
+    pub request_timeout: Option<Duration>,
+    /// How many messages not routed to a request are kept for [`WsClient::recv_raw`], newer ones are
+    /// dropped while the queue is full
+    pub unrouted_queue_size: usize,
+}
+
+impl Default for WsClientConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: None,
+            heartbeat_timeout: Duration::from_secs(10),
+            request_timeout: None,
+            unrouted_queue_size: 1024,
+        }
+    }
+}
+
+/// Set in the seq of the requests sent with `WsClient::send_detached`, whose responses are dropped.
+const DETACHED_SEQ_BIT: u32 = 1 << 31;
+
+/// Where the reader task delivers the incoming messages.
+#[derive(Default)]
+struct WsClientRoutes {
+    /// Requests waiting for their immediate response, by seq
+    pending: HashMap<u32, oneshot::Sender<WsClientResult<Value>>>,
+    /// Stream messages by the seq of the request that started the stream
+    streams_by_seq: HashMap<u32, mpsc::UnboundedSender<WsStreamResponse>>,
+    /// Stream messages by stream code, when no request of their `original_seq` is routed
+    streams_by_code: HashMap<u32, mpsc::UnboundedSender<WsStreamResponse>>,
+    closed: bool,
+}
+
+struct WsClientShared {
+    seq: AtomicU32,
+    routes: Mutex<WsClientRoutes>,
+    /// Cancelled when the connection is closed
+    closed: CancellationToken,
+}
+
+/// `WsClient` is a cloneable handle to a websocket connection to an endpoint server.
+///
+/// A background task reads the connection and routes immediate responses and errors to the
+/// pending request with the same `seq`, so clones can issue requests concurrently. Stream messages
+/// go to the receiver of their request or of their stream code. Anything else is queued for
+/// [`WsClient::recv_raw`]. The connection is closed once every clone is dropped.
+///
+/// Requests fail with a [`WsClientError`], e.g. `WsClientError::Remote` with the error code sent
+/// by the server.
+#[derive(Clone)]
 pub struct WsClient {
-    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
-    seq: u32,
+    shared: Arc<WsClientShared>,
+    tx: mpsc::UnboundedSender<Message>,
+    unrouted: Arc<tokio::sync::Mutex<mpsc::Receiver<WsResponseValue>>>,
+    timeout: Option<Duration>,
 }
 impl WsClient {
     pub async fn new(connect_addr: &str, header: &str) -> Result<Self> {
+        Self::connect(connect_addr, header, WsClientConfig::default()).await
+    }
+
+    /// Connects to `connect_addr`, sending `header` as `Sec-WebSocket-Protocol` (usually the
+    /// authentication of the endpoint server).
+    pub async fn connect(connect_addr: &str, header: &str, config: WsClientConfig) -> Result<Self> {
         let mut req = <&str as IntoClientRequest>::into_client_request(connect_addr)?;
         req.headers_mut()
             .insert("Sec-WebSocket-Protocol", HeaderValue::from_str(header)?);
 
         let (ws_stream, _) = connect_async(req).await.context("Failed to connect to endpoint")?;
+        let shared = Arc::new(WsClientShared {
+            seq: AtomicU32::new(0),
+            routes: Mutex::new(WsClientRoutes::default()),
+            closed: CancellationToken::new(),
+        });
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (unrouted_tx, unrouted_rx) = mpsc::channel(config.unrouted_queue_size.max(1));
+        let timeout = config.request_timeout;
+        tokio::spawn(run_connection(ws_stream, rx, shared.clone(), unrouted_tx, config));
         Ok(Self {
-            stream: ws_stream,
-            seq: 0,
+            shared,
+            tx,
+            unrouted: Arc::new(tokio::sync::Mutex::new(unrouted_rx)),
+            timeout,
         })
     }
-    pub async fn send_req(&mut self, method: u32, params: impl Serialize) -> Result<()> {
-        self.seq += 1;
-        let req = serde_json::to_string(&WsRequestGeneric {
-            method,
-            seq: self.seq,
-            params,
-        })?;
+
+    /// Returns a handle to the same connection whose requests fail with `WsClientError::Timeout`
+    /// if no response arrives within `timeout`, e.g. `client.with_timeout(d).request(req)`.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self.clone()
+        }
+    }
+
+    pub(super) fn next_seq(&self) -> u32 {
+        (self.shared.seq.fetch_add(1, Ordering::Relaxed) + 1) & !DETACHED_SEQ_BIT
+    }
+
+    fn send(&self, method: u32, seq: u32, params: impl Serialize) -> WsClientResult<()> {
+        let req = serde_json::to_string(&WsRequestGeneric { method, seq, params })
+            .map_err(|err| WsClientError::Protocol(format!("Failed to encode request: {}", err)))?;
         debug!("send req: {}", req);
-        self.stream.send(Message::Text(req)).await?;
-        Ok(())
+        self.tx.send(Message::Text(req)).map_err(|_| WsClientError::Closed)
     }
-    pub async fn recv_raw(&mut self) -> Result<WsResponseValue> {
-        let msg = self.stream.next().await.ok_or(eyre!("Connection closed"))??;
-        let resp: WsResponseValue = serde_json::from_str(&msg.to_string())?;
-        Ok(resp)
+
+    /// Sends a request without waiting for its response, which is queued for [`WsClient::recv_raw`].
+    /// Returns the seq of the request.
+    pub async fn send_req(&self, method: u32, params: impl Serialize) -> WsClientResult<u32> {
+        let seq = self.next_seq();
+        self.send(method, seq, params)?;
+        Ok(seq)
     }
-    pub async fn recv_resp<T: DeserializeOwned>(&mut self) -> Result<T> {
-        loop {
-            let msg = self.stream.next().await.ok_or(eyre!("Connection closed"))??;
-            match msg {
-                Message::Text(text) => {
-                    debug!("recv resp: {}", text);
-                    let resp: WsResponseGeneric<T> = serde_json::from_str(&text)?;
-                    match resp {
-                        WsResponseGeneric::Immediate(resp) if resp.seq == self.seq => {
-                            return Ok(resp.params);
-                        }
-                        WsResponseGeneric::Immediate(resp) => {
-                            bail!("Seq mismatch this: {} got: {}", self.seq, resp.seq)
-                        }
-                        WsResponseGeneric::Stream(_) => {
-                            debug!("expect immediate response, got stream")
-                        }
-                        WsResponseGeneric::Forwarded(_) => {
-                            debug!("expect immediate response, got forwarded")
-                        }
-                        WsResponseGeneric::Close => {
-                            bail!("unreachable")
-                        }
-                        WsResponseGeneric::Log(WsLogResponse {
-                            log_id, level, message, ..
-                        }) => match level {
-                            LogLevel::Error => error!(?log_id, "{}", message),
-                            LogLevel::Warn => warn!(?log_id, "{}", message),
-                            LogLevel::Info => info!(?log_id, "{}", message),
-                            LogLevel::Debug => debug!(?log_id, "{}", message),
-                            LogLevel::Trace => trace!(?log_id, "{}", message),
-                            LogLevel::Detail => trace!(?log_id, "{}", message),
-                            LogLevel::Off => {}
-                        },
-                        WsResponseGeneric::Error(err) => {
-                            bail!("Error: {} {:?}", err.code, err.params)
-                        }
+
+    /// Receives the next message that isn't routed to a request or a stream receiver, e.g. the
+    /// response of a request sent with [`WsClient::send_req`]. Up to
+    /// [`WsClientConfig::unrouted_queue_size`] messages are kept until read, later ones are dropped.
+    pub async fn recv_raw(&self) -> WsClientResult<WsResponseValue> {
+        self.unrouted.lock().await.recv().await.ok_or(WsClientError::Closed)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.is_cancelled()
+    }
+
+    /// Waits until the connection is closed, by either side or because the heartbeat timed out.
+    pub async fn closed(&self) {
+        self.shared.closed.cancelled().await
+    }
+
+    /// Returns a token cancelled when the connection is closed, that doesn't keep it open.
+    pub(super) fn closed_token(&self) -> CancellationToken {
+        self.shared.closed.clone()
+    }
+
+    fn remove_routes(&self, seq: u32) {
+        let mut routes = self.shared.routes.lock();
+        routes.pending.remove(&seq);
+        routes.streams_by_seq.remove(&seq);
+    }
+
+    /// Sends a request with `seq` and waits for its immediate response. If `stream` is set, the
+    /// stream messages of the request are routed to it.
+    pub(super) async fn request_routed(
+        &self,
+        seq: u32,
+        method: u32,
+        params: impl Serialize,
+        stream: Option<mpsc::UnboundedSender<WsStreamResponse>>,
+    ) -> WsClientResult<Value> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut routes = self.shared.routes.lock();
+            if routes.closed {
+                return Err(WsClientError::Closed);
+            }
+            routes.pending.insert(seq, tx);
+            if let Some(stream) = stream {
+                routes.streams_by_seq.insert(seq, stream);
+            }
+        }
+        if let Err(err) = self.send(method, seq, params) {
+            self.remove_routes(seq);
+            return Err(err);
+        }
+        let resp = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, rx).await {
+                Ok(resp) => resp,
+                Err(_) => {
+                    self.remove_routes(seq);
+                    return Err(WsClientError::Timeout(timeout));
+                }
+            },
+            None => rx.await,
+        };
+        resp.map_err(|_| WsClientError::Closed)?
+    }
+
+    /// Sends a request and waits for its response as JSON.
+    pub async fn request_raw(&self, method: u32, params: impl Serialize) -> WsClientResult<Value> {
+        self.request_routed(self.next_seq(), method, params, None).await
+    }
+
+    pub async fn request<T: WsRequest>(&self, params: T) -> WsClientResult<T::Response> {
+        let resp = self.request_raw(T::METHOD_ID, params).await?;
+        Ok(serde_json::from_value(resp)?)
+    }
+
+    /// Sends a request that starts a stream, e.g. a subscription, and returns its response along
+    /// with the receiver of its stream messages. The route is removed when the receiver is dropped
+    /// and a message arrives.
+    pub async fn request_with_stream<T: WsRequest>(
+        &self,
+        params: T,
+    ) -> WsClientResult<(T::Response, WsStreamReceiver)> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let resp = self.request_routed(self.next_seq(), T::METHOD_ID, params, Some(tx)).await?;
+        Ok((serde_json::from_value(resp)?, rx))
+    }
+
+    /// Sends a request and ignores its response, which is dropped rather than queued for `recv_raw`.
+    pub(super) fn send_detached(&self, method: u32, params: impl Serialize) -> WsClientResult<()> {
+        self.send(method, self.next_seq() | DETACHED_SEQ_BIT, params)
+    }
+
+    /// Stops routing the stream messages of the request `seq`.
+    pub(super) fn remove_stream(&self, seq: u32) {
+        self.shared.routes.lock().streams_by_seq.remove(&seq);
+    }
+
+    /// Returns a receiver of the stream messages with `stream_code` that aren't routed to the
+    /// request that started them. It replaces any previous receiver of `stream_code`.
+    pub fn streams(&self, stream_code: u32) -> WsStreamReceiver {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.shared.routes.lock().streams_by_code.insert(stream_code, tx);
+        rx
+    }
+
+    pub async fn close(self) -> WsClientResult<()> {
+        self.tx.send(Message::Close(None)).map_err(|_| WsClientError::Closed)
+    }
+}
+
+fn log_message(log: WsLogResponse) {
+    let WsLogResponse {
+        log_id, level, message, ..
+    } = log;
+    match level {
+        LogLevel::Error => error!(?log_id, "{}", message),
+        LogLevel::Warn => warn!(?log_id, "{}", message),
+        LogLevel::Info => info!(?log_id, "{}", message),
+        LogLevel::Debug => debug!(?log_id, "{}", message),
+        LogLevel::Trace => trace!(?log_id, "{}", message),
+        LogLevel::Detail => trace!(?log_id, "{}", message),
+        LogLevel::Off => {}
+    }
+}
+
+/// Routes a message from the server, returning it if nothing is waiting for it.
+fn route_message(shared: &WsClientShared, resp: WsResponseValue) -> Option<WsResponseValue> {
+    let mut routes = shared.routes.lock();
+    match resp {
+        WsResponseGeneric::Immediate(resp) => match routes.pending.remove(&resp.seq) {
+            Some(tx) => {
+                let _ = tx.send(Ok(resp.params));
+                None
+            }
+            None if resp.seq & DETACHED_SEQ_BIT != 0 => None,
+            None => Some(WsResponseGeneric::Immediate(resp)),
+        },
+        WsResponseGeneric::Error(err) => {
+            routes.streams_by_seq.remove(&err.seq);
+            match routes.pending.remove(&err.seq) {
+                Some(tx) => {
+                    let _ = tx.send(Err(err.into()));
+                    None
+                }
+                None if err.seq & DETACHED_SEQ_BIT != 0 => None,
+                None => Some(WsResponseGeneric::Error(err)),
+            }
+        }
+        WsResponseGeneric::Stream(resp) => {
+            let (seq, code) = (resp.original_seq, resp.stream_code);
+            let resp = match routes.streams_by_seq.get(&seq) {
+                Some(tx) => match tx.send(resp) {
+                    Ok(()) => return None,
+                    Err(err) => {
+                        routes.streams_by_seq.remove(&seq);
+                        err.0
+                    }
+                },
+                None => resp,
+            };
+            match routes.streams_by_code.get(&code) {
+                Some(tx) => match tx.send(resp) {
+                    Ok(()) => None,
+                    Err(err) => {
+                        routes.streams_by_code.remove(&code);
+                        Some(WsResponseGeneric::Stream(err.0))
                     }
+                },
+                None => Some(WsResponseGeneric::Stream(resp)),
+            }
+        }
+        WsResponseGeneric::Log(log) => {
+            log_message(log);
+            None
+        }
+        resp => Some(resp),
+    }
+}
+
+async fn tick(heartbeat: &mut Option<Interval>) {
+    match heartbeat {
+        Some(heartbeat) => {
+            heartbeat.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Reads and writes the connection until it is closed, the heartbeat times out or every client
+/// handle is dropped, then fails the pending requests.
+async fn run_connection(
+    mut stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    mut rx: mpsc::UnboundedReceiver<Message>,
+    shared: Arc<WsClientShared>,
+    unrouted: mpsc::Sender<WsResponseValue>,
+    config: WsClientConfig,
+) {
+    let mut heartbeat = config.heartbeat_interval.map(tokio::time::interval);
+    let dead_after = config.heartbeat_interval.unwrap_or_default() + config.heartbeat_timeout;
+    let mut last_seen = Instant::now();
+    loop {
+        tokio::select! {
+            _ = tick(&mut heartbeat) => {
+                if last_seen.elapsed() > dead_after {
+                    warn!("Websocket heartbeat timed out after {:?}", last_seen.elapsed());
+                    break;
+                }
+                if let Err(err) = stream.send(Message::Ping(vec![])).await {
+                    debug!("Failed to send websocket ping: {:?}", err);
+                    break;
                 }
-                Message::Close(_) => {
-                    self.stream.close(None).await?;
-                    bail!("Connection closed")
+            }
+            msg = rx.recv() => {
+                let Some(msg) = msg else {
+                    let _ = stream.close(None).await;
+                    break;
+                };
+                if let Err(err) = stream.send(msg).await {
+                    debug!("Failed to send websocket message: {:?}", err);
+                    break;
                 }
-                _ => {}
             }
+            msg = stream.next() => {
+                last_seen = Instant::now();
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => {
+                        debug!("Failed to read websocket message: {:?}", err);
+                        break;
+                    }
+                };
+                debug!("recv resp: {}", text);
+                let resp: WsResponseValue = match serde_json::from_str(&text) {
+                    Ok(resp) => resp,
+                    Err(err) => {
+                        warn!("Invalid websocket message {}: {:?}", text, err);
+                        continue;
+                    }
+                };
+                if let Some(resp) = route_message(&shared, resp) {
+                    if let Err(err) = unrouted.try_send(resp) {
+                        warn!("Dropped unrouted websocket message, the recv_raw queue is full: {:?}", err.into_inner());
+                    }
+                }
+            }
+        }
+    }
+    let mut routes = shared.routes.lock();
+    routes.closed = true;
+    for (_, tx) in routes.pending.drain() {
+        let _ = tx.send(Err(WsClientError::Closed));
+    }
+    routes.streams_by_seq.clear();
+    routes.streams_by_code.clear();
+    shared.closed.cancel();
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::libs::error_code::ErrorCode;
+    use crate::libs::ws::{WsRequestValue, WsResponseError, WsStreamResponseGeneric, WsSuccessResponseGeneric};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+
+    /// Accepts the protocol requested by the client, as the client requires one.
+    pub(in crate::libs::ws) struct EchoProtocol;
+
+    impl Callback for EchoProtocol {
+        fn on_request(self, req: &Request, mut resp: Response) -> std::result::Result<Response, ErrorResponse> {
+            let protocol = req.headers()["Sec-WebSocket-Protocol"].clone();
+            resp.headers_mut().insert("Sec-WebSocket-Protocol", protocol);
+            Ok(resp)
         }
     }
-    pub async fn request<T: WsRequest>(&mut self, params: T) -> Result<T::Response> {
-        self.send_req(T::METHOD_ID, params).await?;
-        self.recv_resp().await
+
+    async fn next_req(ws: &mut WebSocketStream<TcpStream>) -> Result<WsRequestValue> {
+        loop {
+            if let Some(Message::Text(text)) = ws.next().await.transpose()? {
+                return Ok(serde_json::from_str(&text)?);
+            }
+        }
     }
-    pub async fn close(mut self) -> Result<()> {
-        self.stream.close(None).await?;
+
+    /// Answers the first two requests in reverse order and streams to the first one, fails the
+    /// third one and ignores the others.
+    async fn serve_out_of_order(listener: TcpListener) -> Result<()> {
+        let (stream, _) = listener.accept().await?;
+        let mut ws = tokio_tungstenite::accept_hdr_async(stream, EchoProtocol).await?;
+        let reqs = [next_req(&mut ws).await?, next_req(&mut ws).await?];
+        for req in reqs.iter().rev() {
+            let resp = WsResponseValue::Immediate(WsSuccessResponseGeneric {
+                method: req.method,
+                seq: req.seq,
+                params: req.params.clone(),
+            });
+            ws.send(Message::Text(serde_json::to_string(&resp)?)).await?;
+        }
+        let stream = WsResponseValue::Stream(WsStreamResponseGeneric {
+            original_seq: reqs[0].seq,
+            method: reqs[0].method,
+            stream_seq: 0,
+            stream_code: 7,
+            data: "tick".into(),
+        });
+        ws.send(Message::Text(serde_json::to_string(&stream)?)).await?;
+        let req = next_req(&mut ws).await?;
+        let err = WsResponseValue::Error(WsResponseError {
+            method: req.method,
+            code: 100400,
+            seq: req.seq,
+            log_id: "1".to_owned(),
+            params: "bad".into(),
+        });
+        ws.send(Message::Text(serde_json::to_string(&err)?)).await?;
+        while ws.next().await.is_some() {}
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_concurrent_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_out_of_order(listener));
+
+        let client = WsClient::new(&addr, "0").await.unwrap();
+        let mut streams = client.streams(7);
+        let other = client.clone();
+        let (a, b) = tokio::join!(client.request_raw(1, "a"), other.request_raw(2, "b"));
+        assert_eq!(a.unwrap(), "a");
+        assert_eq!(b.unwrap(), "b");
+        assert_eq!(streams.recv().await.unwrap().data, "tick");
+
+        let err = client.request_raw(3, "c").await.unwrap_err();
+        assert_eq!(err.code(), Some(ErrorCode::new(100400)));
+        let timeout = Duration::from_millis(50);
+        let err = client.with_timeout(timeout).request_raw(4, "d").await.unwrap_err();
+        assert!(matches!(err, WsClientError::Timeout(x) if x == timeout));
+
+        client.close().await.unwrap();
+        server.await.unwrap().unwrap();
+        assert!(matches!(other.request_raw(5, "e").await, Err(WsClientError::Closed)));
+    }
 }
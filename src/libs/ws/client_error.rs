@@ -0,0 +1,98 @@
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use crate::libs::error_code::ErrorCode;
+
+use super::{WsResponseError, WsStreamGap};
+
+pub type WsClientResult<T> = std::result::Result<T, WsClientError>;
+
+/// `WsClientError` is the error of a request made with a [`super::WsClient`].
+#[derive(Debug)]
+pub enum WsClientError {
+    /// The server answered with an error
+    Remote {
+        method: u32,
+        code: ErrorCode,
+        log_id: String,
+        params: Value,
+    },
+    /// The request couldn't be encoded or the server broke the protocol
+    Protocol(String),
+    /// The connection is closed, or was closed before the response arrived
+    Closed,
+    /// No response arrived in time, see [`super::WsClient::with_timeout`]
+    Timeout(Duration),
+    /// The response or stream message doesn't match the expected type
+    Decode(serde_json::Error),
+    /// Stream messages were missed, see [`WsStreamGap`]
+    StreamGap(WsStreamGap),
+}
+
+impl WsClientError {
+    /// The error code sent by the server, if the error is remote.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Self::Remote { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl From<WsResponseError> for WsClientError {
+    fn from(err: WsResponseError) -> Self {
+        Self::Remote {
+            method: err.method,
+            code: ErrorCode::new(err.code),
+            log_id: err.log_id,
+            params: err.params,
+        }
+    }
+}
+
+impl From<serde_json::Error> for WsClientError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Decode(err)
+    }
+}
+
+impl From<WsStreamGap> for WsClientError {
+    fn from(gap: WsStreamGap) -> Self {
+        Self::StreamGap(gap)
+    }
+}
+
+impl Display for WsClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Remote {
+                method,
+                code,
+                log_id,
+                params,
+            } => write!(
+                f,
+                "Error {} on method {} (log_id {}): {}",
+                code.code(),
+                method,
+                log_id,
+                params
+            ),
+            Self::Protocol(msg) => write!(f, "Protocol error: {}", msg),
+            Self::Closed => f.write_str("Connection closed"),
+            Self::Timeout(timeout) => write!(f, "No response within {:?}", timeout),
+            Self::Decode(err) => write!(f, "Failed to decode response: {}", err),
+            Self::StreamGap(gap) => gap.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for WsClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
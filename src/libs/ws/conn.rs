@@ -1,13 +1,29 @@
 use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use tokio_tungstenite::tungstenite::Message;
 
 use super::{ConnectionId, WsConnection};
 
+/// `WebsocketStates` is the registry of the live connections of a server, indexed by connection
+/// id, user id and role.
+///
+/// The user and role indexes are refreshed by [`WebsocketStates::reindex`], which the server calls
+/// once the connection is authenticated. Call it again after changing `user_id` or `role` of a
+/// connection later on, e.g. on login. Until then lookups may miss the connection, but never
+/// return it under its previous user or role.
 #[derive(Default)]
 pub struct WebsocketStates {
     states: Arc<DashMap<ConnectionId, Arc<WsStreamState>>>,
+    by_user: DashMap<i64, HashSet<ConnectionId>>,
+    by_role: DashMap<u32, HashSet<ConnectionId>>,
+    /// The user id and role each connection is indexed under
+    indexed: DashMap<ConnectionId, (i64, u32)>,
 }
 
 impl WebsocketStates {
@@ -16,6 +32,7 @@ impl WebsocketStates {
     }
     pub fn remove(&self, connection_id: u32) {
         self.states.remove(&connection_id);
+        self.unindex(connection_id);
     }
 
     pub fn get_state(&self, connection_id: u32) -> Option<Arc<WsStreamState>> {
@@ -33,9 +50,188 @@ impl WebsocketStates {
         self.states
             .insert(connection_id, Arc::new(WsStreamState { conn, message_queue }));
     }
+
+    /// Indexes the connection under its current `user_id` and `role`.
+    pub fn reindex(&self, connection_id: ConnectionId) {
+        let Some(state) = self.get_state(connection_id) else {
+            return;
+        };
+        let key = (state.conn.get_user_id(), state.conn.get_role());
+        if self.indexed.get(&connection_id).map(|x| *x) == Some(key) {
+            return;
+        }
+        self.unindex(connection_id);
+        self.by_user.entry(key.0).or_default().insert(connection_id);
+        self.by_role.entry(key.1).or_default().insert(connection_id);
+        self.indexed.insert(connection_id, key);
+    }
+    fn unindex(&self, connection_id: ConnectionId) {
+        if let Some((_, (user_id, role))) = self.indexed.remove(&connection_id) {
+            remove_from_index(&self.by_user, user_id, connection_id);
+            remove_from_index(&self.by_role, role, connection_id);
+        }
+    }
+
+    /// Returns the connections of `user_id`, one per open tab or device.
+    pub fn get_user_states(&self, user_id: i64) -> Vec<Arc<WsStreamState>> {
+        self.lookup(&self.by_user, user_id, |x| x.get_user_id())
+    }
+    pub fn get_role_states(&self, role: u32) -> Vec<Arc<WsStreamState>> {
+        self.lookup(&self.by_role, role, |x| x.get_role())
+    }
+    /// Returns the connections indexed under `key` whose `current` key still matches, connections
+    /// changed since they were indexed are moved under their current key instead.
+    fn lookup<K: Eq + Hash>(
+        &self,
+        index: &DashMap<K, HashSet<ConnectionId>>,
+        key: K,
+        current: impl Fn(&WsConnection) -> K,
+    ) -> Vec<Arc<WsStreamState>> {
+        let ids: Vec<ConnectionId> = index
+            .get(&key)
+            .map(|x| x.iter().copied().collect())
+            .unwrap_or_default();
+        let mut states = vec![];
+        for state in ids.into_iter().filter_map(|x| self.get_state(x)) {
+            if current(&state.conn) == key {
+                states.push(state);
+            } else {
+                self.reindex(state.conn.connection_id);
+            }
+        }
+        states
+    }
+
+    /// Lists the live connections, ordered by connection id.
+    pub fn list(&self) -> Vec<WsConnectionInfo> {
+        let mut list: Vec<WsConnectionInfo> = self.states.iter().map(|x| WsConnectionInfo::new(&x.conn)).collect();
+        list.sort_by_key(|x| x.connection_id);
+        list
+    }
+}
+
+fn remove_from_index<K: Eq + Hash>(index: &DashMap<K, HashSet<ConnectionId>>, key: K, connection_id: ConnectionId) {
+    index.remove_if_mut(&key, |_, ids| {
+        ids.remove(&connection_id);
+        ids.is_empty()
+    });
 }
 
 pub struct WsStreamState {
     pub conn: Arc<WsConnection>,
     pub message_queue: tokio::sync::mpsc::Sender<Message>,
 }
+
+/// `WsConnectionInfo` is a snapshot of a live connection, e.g. for an admin listing.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WsConnectionInfo {
+    pub connection_id: ConnectionId,
+    pub user_id: i64,
+    pub role: u32,
+    pub address: SocketAddr,
+    /// Unix timestamp in milliseconds
+    pub connected_at: i64,
+    pub bytes_sent: u64,
+}
+
+impl WsConnectionInfo {
+    pub fn new(conn: &WsConnection) -> Self {
+        Self {
+            connection_id: conn.connection_id,
+            user_id: conn.get_user_id(),
+            role: conn.get_role(),
+            address: conn.address,
+            connected_at: conn.connected_at,
+            bytes_sent: conn.bytes_sent.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::toolbox::Toolbox;
+    use crate::libs::ws::WsResponseValue;
+    use std::sync::atomic::{AtomicI64, AtomicU32, AtomicU64};
+    use tokio::sync::mpsc;
+
+    fn connect(
+        states: &WebsocketStates,
+        connection_id: ConnectionId,
+        user_id: i64,
+        role: u32,
+    ) -> mpsc::Receiver<Message> {
+        let conn = Arc::new(WsConnection {
+            connection_id,
+            user_id: AtomicI64::new(user_id),
+            role: AtomicU32::new(role),
+            address: "127.0.0.1:8000".parse().unwrap(),
+            log_id: 0,
+            connected_at: 0,
+            bytes_sent: AtomicU64::new(0),
+        });
+        let (tx, rx) = mpsc::channel(10);
+        states.insert(connection_id, tx, conn);
+        states.reindex(connection_id);
+        rx
+    }
+
+    fn ids(states: Vec<Arc<WsStreamState>>) -> Vec<ConnectionId> {
+        let mut ids: Vec<_> = states.iter().map(|x| x.conn.connection_id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_registry_indexes() {
+        let states = WebsocketStates::new();
+        let _a = connect(&states, 1, 10, 1);
+        let _b = connect(&states, 2, 10, 2);
+        let _c = connect(&states, 3, 20, 2);
+        assert_eq!(ids(states.get_user_states(10)), vec![1, 2]);
+        assert_eq!(ids(states.get_role_states(2)), vec![2, 3]);
+
+        // logging in as another user
+        states.get_state(2).unwrap().conn.user_id.store(20, Ordering::Relaxed);
+        states.reindex(2);
+        assert_eq!(ids(states.get_user_states(10)), vec![1]);
+        assert_eq!(ids(states.get_user_states(20)), vec![2, 3]);
+
+        // changed without reindexing
+        states.get_state(1).unwrap().conn.user_id.store(20, Ordering::Relaxed);
+        assert!(states.get_user_states(10).is_empty());
+        assert_eq!(ids(states.get_user_states(20)), vec![1, 2, 3]);
+        states.get_state(1).unwrap().conn.user_id.store(10, Ordering::Relaxed);
+        states.reindex(1);
+
+        states.remove(3);
+        assert_eq!(ids(states.get_user_states(20)), vec![2]);
+        assert_eq!(ids(states.get_role_states(2)), vec![2]);
+        let list = states.list();
+        assert_eq!(list.iter().map(|x| x.connection_id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(list[1].user_id, 20);
+    }
+
+    #[test]
+    fn test_toolbox_registry() {
+        let states = Arc::new(WebsocketStates::new());
+        let toolbox = Toolbox::new();
+        toolbox.set_ws_registry(states.clone(), false);
+        let mut a = connect(&states, 1, 10, 1);
+        let mut b = connect(&states, 2, 10, 2);
+        let mut c = connect(&states, 3, 20, 2);
+
+        assert_eq!(toolbox.send_to_user(10, &WsResponseValue::Close), 2);
+        assert!(a.try_recv().is_ok() && b.try_recv().is_ok() && c.try_recv().is_err());
+        assert_eq!(toolbox.broadcast_to_role(2, &WsResponseValue::Close), 2);
+        assert!(a.try_recv().is_err() && b.try_recv().is_ok() && c.try_recv().is_ok());
+
+        assert_eq!(toolbox.disconnect_user(10, "banned"), 2);
+        let Ok(Message::Close(Some(frame))) = a.try_recv() else {
+            panic!("expected a close frame");
+        };
+        assert_eq!(frame.reason, "banned");
+        assert!(!toolbox.disconnect(4, "gone"));
+        assert_eq!(toolbox.list_connections().len(), 3);
+    }
+}
@@ -0,0 +1,392 @@
+use eyre::Result;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::*;
+
+use crate::libs::signal::CANCELLATION_TOKEN;
+
+use super::subscription::WsSubscriptionSource;
+use super::{WsClient, WsClientConfig, WsClientError, WsClientResult, WsRequest, WsStreamRequest};
+use super::{WsStreamResponse, WsSubscription};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WsReconnectConfig {
+    /// The delay before the first reconnection attempt, doubled after each failure
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    /// How long each re-issued subscription waits for its response, the reconnection attempt fails
+    /// if one doesn't arrive in time
+    pub resubscribe_timeout: Duration,
+    /// Configuration of each connection, usually with a heartbeat to detect dead connections
+    pub client: WsClientConfig,
+}
+
+impl Default for WsReconnectConfig {
+    fn default() -> Self {
+        Self {
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            resubscribe_timeout: Duration::from_secs(10),
+            client: WsClientConfig {
+                heartbeat_interval: Some(Duration::from_secs(15)),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WsConnectionState {
+    Connected,
+    /// The connection was lost, `attempt` counts the failed reconnections since
+    Reconnecting { attempt: u32 },
+    /// Closed with [`ReconnectingWsClient::close`] or on shutdown
+    Closed,
+}
+
+/// A subscription re-issued on every new connection.
+struct Resubscription {
+    id: u64,
+    method: u32,
+    params: Value,
+    /// The seq of the request on the current connection
+    seq: u32,
+    tx: mpsc::UnboundedSender<WsStreamResponse>,
+}
+
+struct ReconnectingShared {
+    connect_addr: String,
+    header: String,
+    config: WsReconnectConfig,
+    client: Mutex<Option<WsClient>>,
+    subscriptions: Mutex<Vec<Resubscription>>,
+    next_subscription_id: AtomicU64,
+    state: watch::Sender<WsConnectionState>,
+    shutdown: CancellationToken,
+}
+
+/// `ReconnectingWsClient` keeps a [`WsClient`] connected, reconnecting with exponential backoff
+/// when the connection is lost or its heartbeat times out.
+///
+/// Subscriptions made with [`ReconnectingWsClient::subscribe`] are re-issued after reconnecting,
+/// and their stream messages keep arriving on the same stream, which ends if the server rejects the
+/// re-issued request. Requests are not retried, as they
+/// may not be idempotent: they fail while reconnecting. Watch [`ReconnectingWsClient::state_events`]
+/// to know when the connection is up again.
+#[derive(Clone)]
+pub struct ReconnectingWsClient {
+    shared: Arc<ReconnectingShared>,
+}
+
+impl ReconnectingWsClient {
+    /// Connects to `connect_addr` with `header` as `Sec-WebSocket-Protocol`, sent again on every
+    /// reconnection. Fails if the first connection fails.
+    pub async fn connect(connect_addr: &str, header: &str, config: WsReconnectConfig) -> Result<Self> {
+        let client = WsClient::connect(connect_addr, header, config.client.clone()).await?;
+        let (state, _) = watch::channel(WsConnectionState::Connected);
+        let shared = Arc::new(ReconnectingShared {
+            connect_addr: connect_addr.to_owned(),
+            header: header.to_owned(),
+            config,
+            client: Mutex::new(Some(client.clone())),
+            subscriptions: Mutex::new(vec![]),
+            next_subscription_id: AtomicU64::new(0),
+            state,
+            shutdown: CancellationToken::new(),
+        });
+        tokio::spawn(run_reconnect(Arc::downgrade(&shared), client.closed_token()));
+        Ok(Self { shared })
+    }
+
+    pub fn state(&self) -> WsConnectionState {
+        *self.shared.state.borrow()
+    }
+
+    /// Returns a receiver notified of every change of the connection state.
+    pub fn state_events(&self) -> watch::Receiver<WsConnectionState> {
+        self.shared.state.subscribe()
+    }
+
+    /// Returns the current connection, or fails with `WsClientError::Closed` while reconnecting.
+    pub fn client(&self) -> WsClientResult<WsClient> {
+        self.shared
+            .client
+            .lock()
+            .clone()
+            .filter(|x| !x.is_closed())
+            .ok_or(WsClientError::Closed)
+    }
+
+    pub async fn request<T: WsRequest>(&self, params: T) -> WsClientResult<T::Response> {
+        self.client()?.request(params).await
+    }
+
+    pub async fn request_raw(&self, method: u32, params: impl Serialize) -> WsClientResult<Value> {
+        self.client()?.request_raw(method, params).await
+    }
+
+    /// Sends a stream request, see [`WsClient::subscribe`]. The request is sent again after each
+    /// reconnection, until the subscription is dropped.
+    pub async fn subscribe<T: WsStreamRequest>(&self, req: T) -> WsClientResult<WsSubscription<T>> {
+        let client = self.client()?;
+        let unsubscribe = req.unsubscribe_request();
+        let params = serde_json::to_value(req)
+            .map_err(|err| WsClientError::Protocol(format!("Failed to encode request: {}", err)))?;
+        let seq = client.next_seq();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let resp = client
+            .request_routed(seq, T::METHOD_ID, &params, Some(tx.clone()))
+            .await?;
+        let id = self.shared.next_subscription_id.fetch_add(1, Ordering::Relaxed);
+        self.shared.subscriptions.lock().push(Resubscription {
+            id,
+            method: T::METHOD_ID,
+            params,
+            seq,
+            tx,
+        });
+        let source = WsSubscriptionSource::Reconnecting(ReconnectingSubscription {
+            shared: Arc::downgrade(&self.shared),
+            id,
+        });
+        Ok(WsSubscription::new(source, seq, serde_json::from_value(resp)?, rx, unsubscribe))
+    }
+
+    /// Stops reconnecting and closes the connection.
+    pub async fn close(self) -> WsClientResult<()> {
+        self.shared.shutdown.cancel();
+        self.shared.state.send_replace(WsConnectionState::Closed);
+        let client = self.shared.client.lock().take();
+        if let Some(client) = client {
+            client.close().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Identifies a subscription of a `ReconnectingWsClient`, so that dropping it stops re-issuing it.
+pub(super) struct ReconnectingSubscription {
+    shared: Weak<ReconnectingShared>,
+    id: u64,
+}
+
+impl ReconnectingSubscription {
+    /// Stops re-issuing the subscription, returns the current connection and the seq of the
+    /// subscription on it.
+    pub(super) fn cancel(&self) -> Option<(WsClient, u32)> {
+        let shared = self.shared.upgrade()?;
+        let seq = {
+            let mut subscriptions = shared.subscriptions.lock();
+            let index = subscriptions.iter().position(|x| x.id == self.id)?;
+            subscriptions.remove(index).seq
+        };
+        let client = shared.client.lock().clone()?;
+        Some((client, seq))
+    }
+}
+
+/// Re-issues the live subscriptions on a new connection. Those the server rejects are dropped,
+/// which ends their stream. Fails if one of them times out.
+async fn resubscribe(shared: &ReconnectingShared, client: &WsClient) -> Result<()> {
+    let client = client.with_timeout(shared.config.resubscribe_timeout);
+    let subscriptions: Vec<(u64, u32, Value, mpsc::UnboundedSender<WsStreamResponse>)> = {
+        let mut subscriptions = shared.subscriptions.lock();
+        subscriptions.retain(|x| !x.tx.is_closed());
+        subscriptions
+            .iter()
+            .map(|x| (x.id, x.method, x.params.clone(), x.tx.clone()))
+            .collect()
+    };
+    for (id, method, params, tx) in subscriptions {
+        let seq = client.next_seq();
+        let result = client.request_routed(seq, method, &params, Some(tx)).await;
+        let mut subscriptions = shared.subscriptions.lock();
+        let Some(index) = subscriptions.iter().position(|x| x.id == id) else {
+            continue;
+        };
+        match result {
+            Ok(_) => subscriptions[index].seq = seq,
+            Err(err @ WsClientError::Remote { .. }) => {
+                warn!("Dropping subscription to method {} rejected on reconnect: {}", method, err);
+                subscriptions.remove(index);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+async fn reconnect(shared: &ReconnectingShared) -> Result<WsClient> {
+    let client = WsClient::connect(&shared.connect_addr, &shared.header, shared.config.client.clone()).await?;
+    if let Err(err) = resubscribe(shared, &client).await {
+        let _ = client.close().await;
+        return Err(err);
+    }
+    Ok(client)
+}
+
+/// Waits for the connection to close and reconnects, until the client is closed or dropped. It only
+/// holds weak references, so that dropping every handle closes the connection.
+async fn run_reconnect(shared: Weak<ReconnectingShared>, mut closed: CancellationToken) {
+    loop {
+        let Some(shutdown) = shared.upgrade().map(|x| x.shutdown.clone()) else {
+            return;
+        };
+        tokio::select! {
+            _ = closed.cancelled() => {}
+            _ = shutdown.cancelled() => break,
+            _ = CANCELLATION_TOKEN.cancelled() => break,
+        }
+        let mut attempt = 0;
+        loop {
+            let Some(this) = shared.upgrade() else {
+                return;
+            };
+            this.state.send_replace(WsConnectionState::Reconnecting { attempt });
+            let delay = this
+                .config
+                .min_backoff
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(this.config.max_backoff);
+            let result = tokio::select! {
+                result = async {
+                    tokio::time::sleep(delay).await;
+                    reconnect(&this).await
+                } => result,
+                _ = shutdown.cancelled() => break,
+                _ = CANCELLATION_TOKEN.cancelled() => break,
+            };
+            match result {
+                Ok(new_client) => {
+                    info!("Reconnected to {}", this.connect_addr);
+                    *this.client.lock() = Some(new_client.clone());
+                    this.state.send_replace(WsConnectionState::Connected);
+                    closed = new_client.closed_token();
+                    break;
+                }
+                Err(err) => {
+                    warn!("Failed to reconnect to {}: {:?}", this.connect_addr, err);
+                    attempt += 1;
+                }
+            }
+        }
+        if shutdown.is_cancelled() || CANCELLATION_TOKEN.is_cancelled() {
+            break;
+        }
+    }
+    if let Some(this) = shared.upgrade() {
+        this.state.send_replace(WsConnectionState::Closed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::ws::client::tests::EchoProtocol;
+    use crate::libs::ws::{WsRequestValue, WsResponse, WsResponseError, WsResponseValue};
+    use crate::libs::ws::{WsStreamResponseGeneric, WsSuccessResponseGeneric};
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+
+    /// Subscribes to ticks, rejected on reconnect if set.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct SubscribeReq(bool);
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct SubscribeResp;
+
+    impl WsRequest for SubscribeReq {
+        type Response = SubscribeResp;
+        const METHOD_ID: u32 = 1;
+        const SCHEMA: &'static str = "";
+    }
+
+    impl WsResponse for SubscribeResp {
+        type Request = SubscribeReq;
+    }
+
+    impl WsStreamRequest for SubscribeReq {
+        type Stream = String;
+    }
+
+    /// Answers two subscriptions on two connections, dropping the first one. On the second one, it
+    /// rejects the subscription asking for it and streams on the other one.
+    async fn serve_flaky(listener: TcpListener) -> eyre::Result<()> {
+        for i in 0..2 {
+            let (stream, _) = listener.accept().await?;
+            let mut ws = tokio_tungstenite::accept_hdr_async(stream, EchoProtocol).await?;
+            let mut accepted = 0;
+            for _ in 0..2 {
+                let req = loop {
+                    if let Some(Message::Text(text)) = ws.next().await.transpose()? {
+                        break serde_json::from_str::<WsRequestValue>(&text)?;
+                    }
+                };
+                let resp = if i == 1 && req.params == true {
+                    WsResponseValue::Error(WsResponseError {
+                        method: req.method,
+                        code: 100400,
+                        seq: req.seq,
+                        log_id: String::new(),
+                        params: Value::Null,
+                    })
+                } else {
+                    accepted = req.seq;
+                    WsResponseValue::Immediate(WsSuccessResponseGeneric {
+                        method: req.method,
+                        seq: req.seq,
+                        params: Value::Null,
+                    })
+                };
+                ws.send(Message::Text(serde_json::to_string(&resp)?)).await?;
+            }
+            if i == 1 {
+                let stream = WsResponseValue::Stream(WsStreamResponseGeneric {
+                    original_seq: accepted,
+                    method: SubscribeReq::METHOD_ID,
+                    stream_seq: 0,
+                    stream_code: 1,
+                    data: "tick".into(),
+                });
+                ws.send(Message::Text(serde_json::to_string(&stream)?)).await?;
+                while ws.next().await.is_some() {}
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_resubscribes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_flaky(listener));
+
+        let config = WsReconnectConfig {
+            min_backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let client = ReconnectingWsClient::connect(&addr, "0", config).await.unwrap();
+        let mut events = client.state_events();
+        let mut stream = client.subscribe(SubscribeReq(false)).await.unwrap();
+        let mut rejected = client.subscribe(SubscribeReq(true)).await.unwrap();
+
+        let msg = tokio::time::timeout(Duration::from_secs(5), stream.next()).await.unwrap();
+        assert_eq!(msg.unwrap().unwrap(), "tick");
+        assert_eq!(client.state(), WsConnectionState::Connected);
+        let msg = tokio::time::timeout(Duration::from_secs(5), rejected.next()).await.unwrap();
+        assert!(msg.is_none());
+
+        client.close().await.unwrap();
+        let closed = events.wait_for(|x| *x == WsConnectionState::Closed);
+        tokio::time::timeout(Duration::from_secs(5), closed).await.unwrap().unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
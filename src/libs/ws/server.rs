@@ -5,7 +5,7 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::sync::atomic::AtomicU32;
+use std::sync::atomic::{AtomicU32, AtomicU64};
 use std::sync::Arc;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::sync::mpsc;
@@ -19,9 +19,9 @@ use crate::libs::error_code::ErrorCode;
 use crate::libs::handler::{RequestHandler, RequestHandlerErased};
 use crate::libs::listener::{ConnectionListener, TcpListener, TlsListener};
 use crate::libs::toolbox::{ArcToolbox, RequestContext, Toolbox, TOOLBOX};
-use crate::libs::utils::{get_conn_id, get_log_id};
+use crate::libs::utils::{get_conn_id, get_log_id, get_time_milliseconds};
 use crate::libs::ws::{VerifyProtocol, WsClientSession, WsConnection};
-use crate::model::EndpointSchema;
+use crate::model::{endpoint_json_schema, EndpointSchema, TypeRegistry};
 use crate::libs::ws::client::WsRequest;
 
 use super::{AuthController, ConnectionId, SimpleAuthController, WebsocketStates, WsEndpoint};
@@ -29,6 +29,7 @@ use super::{AuthController, ConnectionId, SimpleAuthController, WebsocketStates,
 pub struct WebsocketServer {
     pub auth_controller: Arc<dyn AuthController>,
     pub handlers: HashMap<u32, WsEndpoint>,
+    pub type_registry: TypeRegistry,
     pub message_receiver: Option<mpsc::Receiver<ConnectionId>>,
     pub toolbox: ArcToolbox,
     pub config: WsServerConfig,
@@ -39,6 +40,7 @@ impl WebsocketServer {
         Self {
             auth_controller: Arc::new(SimpleAuthController),
             handlers: Default::default(),
+            type_registry: Default::default(),
             message_receiver: None,
             toolbox: Toolbox::new(),
             config,
@@ -48,11 +50,17 @@ impl WebsocketServer {
         self.auth_controller = Arc::new(controller);
     }
     pub fn add_handler<T: RequestHandler + 'static>(&mut self, handler: T) {
-        let schema = serde_json::from_str(T::Request::SCHEMA).expect("Invalid schema");
+        let schema = T::Request::schema();
         check_handler::<T>(&schema).expect("Invalid handler");
         self.add_handler_erased(schema, Arc::new(handler))
     }
     pub fn add_handler_erased(&mut self, schema: EndpointSchema, handler: Arc<dyn RequestHandlerErased>) {
+        // derived type names are bare Rust idents, two modules may each define their own `Row`
+        let mut registry = self.type_registry.clone();
+        match registry.register_endpoint(&schema) {
+            Ok(()) => self.type_registry = registry,
+            Err(err) => warn!("Types of {} are not registered: {:?}", schema.name, err),
+        }
         let old = self.handlers.insert(schema.code, WsEndpoint { schema, handler });
         if let Some(old) = old {
             panic!(
@@ -61,6 +69,26 @@ impl WebsocketServer {
             );
         }
     }
+    /// Fills the JSON schema of every handler that has none, once all handlers are added so that
+    /// references resolve regardless of the order of `add_handler`. Called by `listen`.
+    ///
+    /// Endpoints whose types conflict with the types of another endpoint are left without one.
+    pub fn populate_json_schemas(&mut self) {
+        for endpoint in self.handlers.values_mut() {
+            let schema = &mut endpoint.schema;
+            if !schema.json_schema.is_null() {
+                continue;
+            }
+            if let Err(err) = self.type_registry.clone().register_endpoint(schema) {
+                warn!("No JSON schema for {}: {:?}", schema.name, err);
+                continue;
+            }
+            match endpoint_json_schema(schema, &self.type_registry) {
+                Ok(json_schema) => schema.json_schema = json_schema,
+                Err(err) => warn!("Failed to generate the JSON schema of {}: {:?}", schema.name, err),
+            }
+        }
+    }
     async fn handle_ws_handshake_and_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
         self: Arc<Self>,
         addr: SocketAddr,
@@ -88,6 +116,8 @@ impl WebsocketServer {
             role: AtomicU32::new(0),
             address: addr,
             log_id: get_log_id(),
+            connected_at: get_time_milliseconds(),
+            bytes_sent: AtomicU64::new(0),
         });
         debug!(?addr, "New connection handshaken {:?}", conn);
         let headers = rx.recv().await.ok_or_else(|| eyre!("Failed to receive ws headers"))?;
@@ -108,6 +138,8 @@ impl WebsocketServer {
             );
             return Err(err);
         }
+        // the auth controller sets the user and role of the connection
+        states.reindex(conn.connection_id);
         self.handle_session_connection(conn, states, stream, rx).await;
 
         Ok(())
@@ -156,10 +188,11 @@ impl WebsocketServer {
         }
     }
 
-    async fn listen_impl<T: ConnectionListener + 'static>(self, listener: Arc<T>) -> Result<()> {
+    async fn listen_impl<T: ConnectionListener + 'static>(mut self, listener: Arc<T>) -> Result<()> {
+        self.populate_json_schemas();
         let states = Arc::new(WebsocketStates::new());
         self.toolbox
-            .set_ws_states(states.clone_states(), self.config.header_only);
+            .set_ws_registry(Arc::clone(&states), self.config.header_only);
         let this = Arc::new(self);
         let local_set = LocalSet::new();
         let (mut sigterm, mut sigint) = crate::libs::signal::init_signals()?;
@@ -250,8 +283,46 @@ pub struct WsServerConfig {
     pub insecure: bool,
     #[serde(default)]
     pub debug: bool,
+    /// Validate request params against the endpoint schema before dispatching them to the handler
+    #[serde(default)]
+    pub validate_requests: bool,
     #[serde(skip)]
     pub header_only: bool,
     #[serde(skip)]
     pub allow_cors_urls: Arc<Option<Vec<String>>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::model::{Field, Type};
+
+    struct Noop;
+
+    #[async_trait(?Send)]
+    impl RequestHandlerErased for Noop {
+        async fn handle(&self, _toolbox: &ArcToolbox, _ctx: RequestContext, _req: Value) {}
+    }
+
+    fn endpoint(name: &str, code: u32, ty: Type) -> EndpointSchema {
+        EndpointSchema::new(name, code, vec![Field::new("row", ty)], vec![])
+    }
+
+    #[test]
+    fn test_populate_json_schemas() {
+        let mut server = WebsocketServer::new(Default::default());
+        let row = Type::struct_("Row", vec![Field::new("id", Type::BigInt)]);
+        let other_row = Type::struct_("Row", vec![Field::new("name", Type::String)]);
+        // the reference is added before its definition
+        server.add_handler_erased(endpoint("Foo", 1, Type::struct_ref("Row")), Arc::new(Noop));
+        server.add_handler_erased(endpoint("Bar", 2, row), Arc::new(Noop));
+        server.add_handler_erased(endpoint("Baz", 3, other_row), Arc::new(Noop));
+        server.populate_json_schemas();
+        assert_eq!(server.handlers[&1].schema.json_schema["$defs"]["Row"]["required"], serde_json::json!(["id"]));
+        assert!(!server.handlers[&2].schema.json_schema.is_null());
+        assert!(server.handlers[&3].schema.json_schema.is_null());
+    }
+}
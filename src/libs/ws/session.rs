@@ -2,6 +2,7 @@ use eyre::Result;
 use futures::StreamExt;
 use futures::{Sink, SinkExt, Stream};
 use serde_json::Value;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio_tungstenite::tungstenite::Message;
@@ -10,7 +11,7 @@ use tracing::*;
 use crate::libs::error_code::ErrorCode;
 use crate::libs::toolbox::{RequestContext, TOOLBOX};
 
-use super::{request_error_to_resp, WebsocketServer, WsConnection, WsRequestValue};
+use super::{request_error_to_resp, validate_params, WebsocketServer, WsConnection, WsRequestValue};
 pub struct WsClientSession<WS> {
     conn_info: Arc<WsConnection>,
     conn: WS,
@@ -119,6 +120,20 @@ impl<
                 return Ok(true);
             }
         };
+        if self.server.config.validate_requests {
+            let violations = validate_params(&handler.schema, &self.server.type_registry, &req.params);
+            if !violations.is_empty() {
+                self.server.toolbox.send(
+                    context.connection_id,
+                    request_error_to_resp(
+                        &context,
+                        ErrorCode::new(100400), // BadRequest
+                        serde_json::to_value(violations)?,
+                    ),
+                );
+                return Ok(true);
+            }
+        }
         let handler = handler.handler.clone();
         let toolbox = self.server.toolbox.clone();
         tokio::task::spawn_local(async move {
@@ -136,8 +151,10 @@ impl<
                 msg = self.rx.recv() => {
                     // info!(?conn_id, ?msg, "Received message to send");
                     if let Some(msg) = msg {
+                        let close = msg.is_close();
                         self.send_message(msg).await?;
-                        if self.server.config.header_only {
+                        // don't wait for the client to answer the close handshake
+                        if close || self.server.config.header_only {
                             break;
                         }
                     } else {
@@ -164,6 +181,7 @@ impl<
     }
     async fn send_message(&mut self, msg: Message) -> Result<()> {
         // info!(?msg, "Sending message");
+        self.conn_info.bytes_sent.fetch_add(msg.len() as u64, Ordering::Relaxed);
         self.conn.send(msg).await?;
         Ok(())
     }
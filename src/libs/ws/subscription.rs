@@ -0,0 +1,274 @@
+use futures::Stream;
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+use super::reconnect::ReconnectingSubscription;
+use super::{WsClient, WsClientResult, WsStreamReceiver, WsStreamRequest};
+
+/// `WsStreamGap` is yielded by a [`WsSubscription`] as `WsClientError::StreamGap` when `stream_seq`
+/// skipped values, i.e. the server dropped messages, e.g. because the connection was too slow. The
+/// stream continues after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WsStreamGap {
+    /// The `stream_seq` following the last message received
+    pub expected: u32,
+    pub received: u32,
+}
+
+impl WsStreamGap {
+    /// The number of messages missed.
+    pub fn missed(&self) -> u32 {
+        self.received.wrapping_sub(self.expected)
+    }
+}
+
+impl Display for WsStreamGap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Missed {} stream messages, expected stream_seq {} got {}",
+            self.missed(),
+            self.expected,
+            self.received
+        )
+    }
+}
+
+impl std::error::Error for WsStreamGap {}
+
+/// `WsSubscription` is the stream of typed messages of a [`WsStreamRequest`], created with
+/// [`WsClient::subscribe`] or [`ReconnectingWsClient::subscribe`].
+///
+/// Messages that fail to decode are yielded as errors, and so are gaps in `stream_seq`, which starts
+/// from 0, as a [`WsStreamGap`] before the message that revealed them. The stream ends when the
+/// connection is closed. Dropping it stops routing its messages and sends the unsubscribe request,
+/// if any.
+///
+/// [`ReconnectingWsClient::subscribe`]: super::ReconnectingWsClient::subscribe
+pub struct WsSubscription<T: WsStreamRequest> {
+    source: WsSubscriptionSource,
+    seq: u32,
+    response: T::Response,
+    rx: WsStreamReceiver,
+    next_stream_seq: Option<u32>,
+    /// A message received after a gap, yielded after the gap
+    pending: Option<T::Stream>,
+    unsubscribe: Option<(u32, Value)>,
+}
+
+/// Where the messages of a [`WsSubscription`] are routed from.
+pub(super) enum WsSubscriptionSource {
+    Client(WsClient),
+    /// Re-issued on every new connection, with a new seq
+    Reconnecting(ReconnectingSubscription),
+}
+
+impl<T: WsStreamRequest> WsSubscription<T> {
+    pub(super) fn new(
+        source: WsSubscriptionSource,
+        seq: u32,
+        response: T::Response,
+        rx: WsStreamReceiver,
+        unsubscribe: Option<(u32, Value)>,
+    ) -> Self {
+        Self {
+            source,
+            seq,
+            response,
+            rx,
+            next_stream_seq: Some(0),
+            pending: None,
+            unsubscribe,
+        }
+    }
+
+    /// The immediate response of the request.
+    pub fn response(&self) -> &T::Response {
+        &self.response
+    }
+
+    /// The seq of the request, the `original_seq` of its stream messages. It changes when a
+    /// subscription of a `ReconnectingWsClient` is re-issued.
+    pub fn seq(&self) -> u32 {
+        self.seq
+    }
+}
+
+impl<T> Stream for WsSubscription<T>
+where
+    T: WsStreamRequest,
+    T::Response: Unpin,
+    T::Stream: Unpin,
+{
+    type Item = WsClientResult<T::Stream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(data) = this.pending.take() {
+            return Poll::Ready(Some(Ok(data)));
+        }
+        let Some(resp) = std::task::ready!(this.rx.poll_recv(cx)) else {
+            return Poll::Ready(None);
+        };
+        if resp.original_seq != this.seq {
+            // re-issued after reconnecting, the stream starts over
+            this.seq = resp.original_seq;
+            this.next_stream_seq = Some(0);
+        }
+        let expected = this.next_stream_seq.replace(resp.stream_seq.wrapping_add(1));
+        let data = match serde_json::from_value(resp.data) {
+            Ok(data) => data,
+            Err(err) => return Poll::Ready(Some(Err(err.into()))),
+        };
+        match expected {
+            Some(expected) if expected != resp.stream_seq => {
+                this.pending = Some(data);
+                let gap = WsStreamGap {
+                    expected,
+                    received: resp.stream_seq,
+                };
+                Poll::Ready(Some(Err(gap.into())))
+            }
+            _ => Poll::Ready(Some(Ok(data))),
+        }
+    }
+}
+
+impl<T: WsStreamRequest> Drop for WsSubscription<T> {
+    fn drop(&mut self) {
+        let (client, seq) = match &self.source {
+            WsSubscriptionSource::Client(client) => (client.clone(), self.seq),
+            WsSubscriptionSource::Reconnecting(subscription) => match subscription.cancel() {
+                Some(current) => current,
+                None => return,
+            },
+        };
+        client.remove_stream(seq);
+        if let Some((method, params)) = self.unsubscribe.take() {
+            // fails only if the connection is closed, when there is nothing to unsubscribe from
+            let _ = client.send_detached(method, params);
+        }
+    }
+}
+
+impl WsClient {
+    /// Sends a stream request, e.g. a subscription, and returns the stream of its typed messages.
+    pub async fn subscribe<T: WsStreamRequest>(&self, req: T) -> WsClientResult<WsSubscription<T>> {
+        let unsubscribe = req.unsubscribe_request();
+        let seq = self.next_seq();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let resp = self.request_routed(seq, T::METHOD_ID, req, Some(tx)).await?;
+        Ok(WsSubscription::new(
+            WsSubscriptionSource::Client(self.clone()),
+            seq,
+            serde_json::from_value(resp)?,
+            rx,
+            unsubscribe,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::ws::client::tests::EchoProtocol;
+    use crate::libs::ws::WsClientError;
+    use crate::libs::ws::{WsRequest, WsRequestValue, WsResponse, WsResponseValue};
+    use crate::libs::ws::{WsStreamResponseGeneric, WsSuccessResponseGeneric};
+    use futures::{SinkExt, StreamExt};
+    use serde::{Deserialize, Serialize};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::WebSocketStream;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct TickerReq;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct TickerResp;
+
+    impl WsRequest for TickerReq {
+        type Response = TickerResp;
+        const METHOD_ID: u32 = 1;
+        const SCHEMA: &'static str = "";
+    }
+
+    impl WsResponse for TickerResp {
+        type Request = TickerReq;
+    }
+
+    impl WsStreamRequest for TickerReq {
+        type Stream = u32;
+
+        fn unsubscribe_request(&self) -> Option<(u32, Value)> {
+            Some((2, Value::Null))
+        }
+    }
+
+    async fn next_req(ws: &mut WebSocketStream<TcpStream>) -> eyre::Result<WsRequestValue> {
+        loop {
+            if let Some(Message::Text(text)) = ws.next().await.transpose()? {
+                return Ok(serde_json::from_str(&text)?);
+            }
+        }
+    }
+
+    /// Answers the subscription, streams with a gap and returns the method of the next request,
+    /// which it answers before an unrouted response.
+    async fn serve_ticker(listener: TcpListener) -> eyre::Result<u32> {
+        let (stream, _) = listener.accept().await?;
+        let mut ws = tokio_tungstenite::accept_hdr_async(stream, EchoProtocol).await?;
+        let req = next_req(&mut ws).await?;
+        let mut msgs = vec![WsResponseValue::Immediate(WsSuccessResponseGeneric {
+            method: req.method,
+            seq: req.seq,
+            params: Value::Null,
+        })];
+        for stream_seq in [0, 1, 3] {
+            msgs.push(WsResponseValue::Stream(WsStreamResponseGeneric {
+                original_seq: req.seq,
+                method: req.method,
+                stream_seq,
+                stream_code: 1,
+                data: stream_seq.into(),
+            }));
+        }
+        for msg in msgs {
+            ws.send(Message::Text(serde_json::to_string(&msg)?)).await?;
+        }
+        let req = next_req(&mut ws).await?;
+        for seq in [req.seq, 100] {
+            let resp = WsResponseValue::Immediate(WsSuccessResponseGeneric {
+                method: req.method,
+                seq,
+                params: Value::Null,
+            });
+            ws.send(Message::Text(serde_json::to_string(&resp)?)).await?;
+        }
+        Ok(req.method)
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_ticker(listener));
+
+        let client = WsClient::new(&addr, "0").await.unwrap();
+        let mut ticker = client.subscribe(TickerReq).await.unwrap();
+        assert_eq!(ticker.next().await.unwrap().unwrap(), 0);
+        assert_eq!(ticker.next().await.unwrap().unwrap(), 1);
+        let gap = ticker.next().await.unwrap().unwrap_err();
+        assert!(matches!(gap, WsClientError::StreamGap(gap) if gap.missed() == 1));
+        assert_eq!(ticker.next().await.unwrap().unwrap(), 3);
+
+        drop(ticker);
+        assert_eq!(server.await.unwrap().unwrap(), 2);
+        // the response to the unsubscribe request is dropped
+        let resp = client.recv_raw().await.unwrap();
+        assert!(matches!(resp, WsResponseValue::Immediate(resp) if resp.seq == 100));
+    }
+}
@@ -0,0 +1,213 @@
+use std::net::IpAddr;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::*;
+use serde_json::Value;
+
+use crate::model::{
+    EndpointSchema, Field, Type, TypeRegistry, BLOCKCHAIN_ADDRESS_PATTERN, BLOCKCHAIN_DECIMAL_PATTERN,
+    BLOCKCHAIN_TRANSACTION_HASH_PATTERN,
+};
+
+lazy_static! {
+    static ref BLOCKCHAIN_DECIMAL: Regex = Regex::new(BLOCKCHAIN_DECIMAL_PATTERN).unwrap();
+    static ref BLOCKCHAIN_ADDRESS: Regex = Regex::new(BLOCKCHAIN_ADDRESS_PATTERN).unwrap();
+    static ref BLOCKCHAIN_TRANSACTION_HASH: Regex = Regex::new(BLOCKCHAIN_TRANSACTION_HASH_PATTERN).unwrap();
+    static ref UUID: Regex =
+        Regex::new("^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$").unwrap();
+}
+
+/// `ValidationViolation` is a single mismatch between request params and the endpoint schema.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ValidationViolation {
+    /// The path of the offending value (e.g. `orders[0].price`)
+    pub path: String,
+    /// What is wrong with the value
+    pub message: String,
+}
+
+struct Validator<'a> {
+    registry: &'a TypeRegistry,
+    violations: Vec<ValidationViolation>,
+}
+
+impl Validator<'_> {
+    fn violation(&mut self, path: &str, message: impl Into<String>) {
+        self.violations.push(ValidationViolation {
+            path: if path.is_empty() { ".".to_owned() } else { path.to_owned() },
+            message: message.into(),
+        });
+    }
+
+    fn expect(&mut self, path: &str, ok: bool, expected: &str, value: &Value) {
+        if !ok {
+            self.violation(path, format!("expected {}, got {}", expected, value));
+        }
+    }
+
+    fn string_matching(&mut self, path: &str, value: &Value, expected: &str, check: impl Fn(&str) -> bool) {
+        let ok = value.as_str().map(check).unwrap_or_default();
+        self.expect(path, ok, expected, value)
+    }
+
+    fn fields(&mut self, path: &str, fields: &[Field], value: &Value) {
+        let Some(object) = value.as_object() else {
+            self.violation(path, format!("expected object, got {}", value));
+            return;
+        };
+        for field in fields {
+            let key = field.serialized_name();
+            let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+            match object.get(&key) {
+                Some(value) => self.value(&path, &field.ty, value),
+                None if matches!(field.ty, Type::Optional(_)) => {}
+                None => self.violation(&path, "missing required field"),
+            }
+        }
+    }
+
+    fn items(&mut self, path: &str, ty: &Type, value: &Value) {
+        let Some(items) = value.as_array() else {
+            self.violation(path, format!("expected array, got {}", value));
+            return;
+        };
+        for (i, item) in items.iter().enumerate() {
+            self.value(&format!("{}[{}]", path, i), ty, item);
+        }
+    }
+
+    fn value(&mut self, path: &str, ty: &Type, value: &Value) {
+        match ty {
+            Type::Optional(_) if value.is_null() => {}
+            Type::Optional(ty) => self.value(path, ty, value),
+            Type::Int => {
+                let ok = value.as_i64().map(|x| i32::try_from(x).is_ok()).unwrap_or_default();
+                self.expect(path, ok, "32-bit integer", value)
+            }
+            Type::BigInt | Type::TimeStampMs => {
+                self.expect(path, value.is_i64() || value.is_u64(), "integer", value)
+            }
+            Type::Numeric => self.expect(path, value.is_number(), "number", value),
+            Type::Boolean => self.expect(path, value.is_boolean(), "boolean", value),
+            Type::String => self.expect(path, value.is_string(), "string", value),
+            Type::Date => self.string_matching(path, value, "date (YYYY-MM-DD)", |x| {
+                chrono::NaiveDate::parse_from_str(x, "%Y-%m-%d").is_ok()
+            }),
+            Type::UUID => self.string_matching(path, value, "UUID", |x| UUID.is_match(x)),
+            Type::Inet => self.string_matching(path, value, "IP address", |x| x.parse::<IpAddr>().is_ok()),
+            Type::Bytea => self.items(path, &Type::Int, value),
+            Type::Object => self.expect(path, value.is_object(), "object", value),
+            Type::Unit => self.expect(path, value.is_null(), "null", value),
+            Type::Vec(ty) => self.items(path, ty, value),
+            Type::Struct { fields, .. } => self.fields(path, fields, value),
+            Type::DataTable { fields, .. } => self.items(path, &Type::struct_("", fields.clone()), value),
+            Type::Enum { variants, .. } => {
+                let names: Vec<String> = variants.iter().map(|x| x.serialized_name()).collect();
+                let ok = value.as_str().map(|x| names.iter().any(|name| name == x)).unwrap_or_default();
+                if !ok {
+                    self.violation(path, format!("expected one of {:?}, got {}", names, value));
+                }
+            }
+            Type::StructRef(name) | Type::EnumRef(name) | Type::DataTableIdentifer { name } => {
+                match self.registry.resolve(ty) {
+                    Some(resolved) => {
+                        let resolved = resolved.clone();
+                        self.value(path, &resolved, value)
+                    }
+                    None => self.violation(path, format!("unknown type {}", name)),
+                }
+            }
+            Type::BlockchainDecimal => {
+                let ok = value.is_number()
+                    || value
+                        .as_str()
+                        .map(|x| BLOCKCHAIN_DECIMAL.is_match(x))
+                        .unwrap_or_default();
+                self.expect(path, ok, "decimal", value)
+            }
+            Type::BlockchainAddress => {
+                self.string_matching(path, value, "blockchain address", |x| BLOCKCHAIN_ADDRESS.is_match(x))
+            }
+            Type::BlockchainTransactionHash => self.string_matching(path, value, "transaction hash", |x| {
+                BLOCKCHAIN_TRANSACTION_HASH.is_match(x)
+            }),
+        }
+    }
+}
+
+/// Validates request params against the parameters of the endpoint, resolving references with
+/// `registry`. Returns every violation found, an empty list means the params are valid.
+///
+/// Only the keys and enum variant names the handler deserializes are accepted, see
+/// [`Field::serialized_name`] and [`EnumVariant::serialized_name`].
+///
+/// [`EnumVariant::serialized_name`]: crate::model::EnumVariant::serialized_name
+pub fn validate_params(schema: &EndpointSchema, registry: &TypeRegistry, params: &Value) -> Vec<ValidationViolation> {
+    let mut validator = Validator {
+        registry,
+        violations: vec![],
+    };
+    validator.fields("", &schema.parameters, params);
+    validator.violations
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::model::EnumVariant;
+
+    #[test]
+    fn test_validate_params() {
+        let side = Type::enum_("side", vec![EnumVariant::new("buy", 1), EnumVariant::new("sell", 2)]);
+        let mut registry = TypeRegistry::new();
+        registry.register(&side).unwrap();
+        let schema = EndpointSchema::new(
+            "UserPlaceOrder",
+            10040,
+            vec![
+                Field::new("user_id", Type::BigInt),
+                Field::new("side", Type::enum_ref("side")),
+                Field::new("address", Type::BlockchainAddress),
+                Field::new("amounts", Type::vec(Type::Int)),
+                Field::new("note", Type::optional(Type::String)),
+            ],
+            vec![],
+        );
+
+        let params = json!({
+            "userId": 1,
+            "side": "Buy",
+            "address": "0x52908400098527886E0F7030069857D2E4169EE7",
+            "amounts": [1, 2],
+        });
+        assert_eq!(validate_params(&schema, &registry, &params), vec![]);
+
+        // the handler doesn't deserialize snake_case keys and variant values either
+        let params = json!({
+            "user_id": 1,
+            "side": 2,
+            "address": "0x52908400098527886E0F7030069857D2E4169EE7",
+            "amounts": [],
+        });
+        let paths: Vec<String> = validate_params(&schema, &registry, &params)
+            .into_iter()
+            .map(|x| x.path)
+            .collect();
+        assert_eq!(paths, vec!["userId", "side"]);
+
+        let params = json!({
+            "side": "hold",
+            "address": "0x1234",
+            "amounts": [1, "2"],
+            "note": 3,
+        });
+        let paths: Vec<String> = validate_params(&schema, &registry, &params)
+            .into_iter()
+            .map(|x| x.path)
+            .collect();
+        assert_eq!(paths, vec!["userId", "side", "address", "amounts[1]", "note"]);
+    }
+}
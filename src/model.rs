@@ -1,8 +1,15 @@
 pub mod endpoint;
+pub mod json_schema;
 pub mod pg_func;
+pub mod reflect;
+pub mod registry;
 pub mod service;
 pub mod types;
 pub use endpoint::*;
+pub use endpoint_libs_derive::{Endpoint, EndpointEnum, EndpointStruct};
+pub use json_schema::*;
 pub use pg_func::*;
+pub use reflect::*;
+pub use registry::*;
 pub use service::*;
 pub use types::*;
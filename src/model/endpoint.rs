@@ -24,7 +24,7 @@ pub struct EndpointSchema {
     #[serde(default)]
     pub description: String,
 
-    /// The JSON schema of the endpoint (`Default::default()`)
+    /// The JSON schema of the endpoint, filled by `Service::populate_json_schemas` (`Default::default()`)
     #[serde(default)]
     pub json_schema: serde_json::Value,
 }
@@ -0,0 +1,217 @@
+use convert_case::{Case, Casing};
+use eyre::{bail, ContextCompat, Result};
+use serde_json::{json, Map, Value};
+
+use crate::model::{EndpointSchema, Field, Service, Type, TypeRegistry};
+
+/// The dialect of the generated JSON schemas.
+pub const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// The pattern of a `Type::BlockchainDecimal` string.
+pub const BLOCKCHAIN_DECIMAL_PATTERN: &str = r"^-?[0-9]+(\.[0-9]+)?$";
+/// The pattern of a `Type::BlockchainAddress` string.
+pub const BLOCKCHAIN_ADDRESS_PATTERN: &str = "^0x[0-9a-fA-F]{40}$";
+/// The pattern of a `Type::BlockchainTransactionHash` string.
+pub const BLOCKCHAIN_TRANSACTION_HASH_PATTERN: &str = "^0x[0-9a-fA-F]{64}$";
+
+/// Builds the JSON schemas of types, collecting named types into `$defs`.
+struct JsonSchemaBuilder<'a> {
+    registry: &'a TypeRegistry,
+    defs: Map<String, Value>,
+}
+
+impl<'a> JsonSchemaBuilder<'a> {
+    fn new(registry: &'a TypeRegistry) -> Self {
+        Self {
+            registry,
+            defs: Map::new(),
+        }
+    }
+
+    fn reference(&mut self, name: &str, ty: &Type) -> Result<Value> {
+        let key = name.to_case(Case::Pascal);
+        if !self.defs.contains_key(&key) {
+            // insert a placeholder first so recursive types terminate
+            self.defs.insert(key.clone(), Value::Null);
+            let def = match ty {
+                Type::Struct { fields, .. } | Type::DataTable { fields, .. } => self.object(fields)?,
+                Type::Enum { variants, .. } => json!({
+                    "type": "string",
+                    "enum": variants.iter().map(|x| x.serialized_name()).collect::<Vec<_>>(),
+                }),
+                _ => bail!("Type {} is not a named type: {:?}", name, ty),
+            };
+            self.defs.insert(key.clone(), def);
+        }
+        Ok(json!({ "$ref": format!("#/$defs/{}", key) }))
+    }
+
+    fn resolve_ref(&mut self, name: &str) -> Result<Value> {
+        let ty = self
+            .registry
+            .get(name)
+            .with_context(|| format!("Unknown type reference {}", name))?;
+        self.reference(name, ty)
+    }
+
+    fn object(&mut self, fields: &[Field]) -> Result<Value> {
+        let mut properties = Map::new();
+        let mut required = vec![];
+        for field in fields {
+            let key = field.serialized_name();
+            if !matches!(field.ty, Type::Optional(_)) {
+                required.push(Value::String(key.clone()));
+            }
+            properties.insert(key, self.ty(&field.ty)?);
+        }
+        Ok(json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        }))
+    }
+
+    fn ty(&mut self, ty: &Type) -> Result<Value> {
+        Ok(match ty {
+            Type::Date => json!({ "type": "string", "format": "date" }),
+            Type::Int => json!({ "type": "integer", "minimum": i32::MIN, "maximum": i32::MAX }),
+            Type::BigInt => json!({ "type": "integer" }),
+            Type::Numeric => json!({ "type": "number" }),
+            Type::Boolean => json!({ "type": "boolean" }),
+            Type::String => json!({ "type": "string" }),
+            Type::Bytea => json!({ "type": "array", "items": { "type": "integer", "minimum": 0, "maximum": 255 } }),
+            Type::UUID => json!({ "type": "string", "format": "uuid" }),
+            Type::Inet => json!({ "type": "string", "anyOf": [{ "format": "ipv4" }, { "format": "ipv6" }] }),
+            Type::Struct { name, .. } | Type::Enum { name, .. } => self.reference(name, ty)?,
+            Type::StructRef(name) | Type::EnumRef(name) => self.resolve_ref(name)?,
+            Type::Object => json!({ "type": "object" }),
+            Type::DataTable { name, .. } => json!({ "type": "array", "items": self.reference(name, ty)? }),
+            Type::DataTableIdentifer { name } => json!({ "type": "array", "items": self.resolve_ref(name)? }),
+            Type::Vec(ty) => json!({ "type": "array", "items": self.ty(ty)? }),
+            Type::Unit => json!({ "type": "null" }),
+            Type::Optional(ty) => json!({ "anyOf": [self.ty(ty)?, { "type": "null" }] }),
+            Type::TimeStampMs => json!({ "type": "integer", "description": "Unix timestamp in milliseconds" }),
+            Type::BlockchainDecimal => json!({ "type": "string", "pattern": BLOCKCHAIN_DECIMAL_PATTERN }),
+            Type::BlockchainAddress => json!({ "type": "string", "pattern": BLOCKCHAIN_ADDRESS_PATTERN }),
+            Type::BlockchainTransactionHash => {
+                json!({ "type": "string", "pattern": BLOCKCHAIN_TRANSACTION_HASH_PATTERN })
+            }
+        })
+    }
+}
+
+/// Generates the JSON schema (draft 2020-12) of the endpoint: an object whose `parameters`,
+/// `returns` and `stream_response` properties describe the request, the response and the stream
+/// messages, with named types shared through `$defs`.
+pub fn endpoint_json_schema(endpoint: &EndpointSchema, registry: &TypeRegistry) -> Result<Value> {
+    let mut builder = JsonSchemaBuilder::new(registry);
+    let mut properties = Map::new();
+    properties.insert("parameters".to_owned(), builder.object(&endpoint.parameters)?);
+    properties.insert("returns".to_owned(), builder.object(&endpoint.returns)?);
+    if let Some(ty) = &endpoint.stream_response {
+        properties.insert("stream_response".to_owned(), builder.ty(ty)?);
+    }
+    Ok(json!({
+        "$schema": JSON_SCHEMA_DIALECT,
+        "title": endpoint.name,
+        "description": endpoint.description,
+        "type": "object",
+        "properties": properties,
+        "$defs": builder.defs,
+    }))
+}
+
+impl Service {
+    /// Fills `json_schema` of every endpoint, resolving references against the types of the service.
+    pub fn populate_json_schemas(&mut self) -> Result<()> {
+        let registry = TypeRegistry::from_service(self)?;
+        for endpoint in &mut self.endpoints {
+            endpoint.json_schema = endpoint_json_schema(endpoint, &registry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{EndpointEnum, EndpointStruct, EndpointType, EnumVariant};
+
+    #[test]
+    fn test_populate_json_schemas() {
+        let side = Type::enum_("side", vec![EnumVariant::new("buy", 1), EnumVariant::new("sell", 2)]);
+        let mut service = Service::new(
+            "user",
+            1,
+            vec![
+                EndpointSchema::new(
+                    "UserPlaceOrder",
+                    10040,
+                    vec![Field::new("side", side), Field::new("price", Type::optional(Type::Numeric))],
+                    vec![],
+                ),
+                EndpointSchema::new(
+                    "UserListOrders",
+                    10041,
+                    vec![Field::new("side", Type::enum_ref("side"))],
+                    vec![],
+                ),
+            ],
+        );
+        service.populate_json_schemas().unwrap();
+        let schema = &service.endpoints[1].json_schema;
+        assert_eq!(schema["$schema"], JSON_SCHEMA_DIALECT);
+        assert_eq!(schema["properties"]["parameters"]["required"], json!(["side"]));
+        assert_eq!(
+            schema["properties"]["parameters"]["properties"]["side"],
+            json!({ "$ref": "#/$defs/Side" })
+        );
+        assert_eq!(schema["$defs"]["Side"]["enum"], json!(["Buy", "Sell"]));
+
+        let schema = &service.endpoints[0].json_schema;
+        assert_eq!(
+            schema["properties"]["parameters"]["properties"]["price"],
+            json!({ "anyOf": [{ "type": "number" }, { "type": "null" }] })
+        );
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, EndpointEnum)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    enum OrderType {
+        StopLimit = 1,
+        #[serde(rename = "mkt")]
+        Market,
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, EndpointStruct)]
+    #[serde(rename_all = "camelCase")]
+    struct Order {
+        order_type: OrderType,
+        #[serde(rename = "PX")]
+        price: f64,
+    }
+
+    #[test]
+    fn test_derived_serialized_names() {
+        let endpoint = EndpointSchema::new("Foo", 1, vec![Field::new("order", Order::endpoint_type())], vec![]);
+        let schema = endpoint_json_schema(&endpoint, &TypeRegistry::new()).unwrap();
+        assert_eq!(schema["$defs"]["Order"]["required"], json!(["orderType", "PX"]));
+        assert_eq!(schema["$defs"]["OrderType"]["enum"], json!(["STOP_LIMIT", "mkt"]));
+    }
+
+    #[test]
+    fn test_unknown_reference() {
+        let endpoint = EndpointSchema::new("Foo", 1, vec![Field::new("bar", Type::struct_ref("Bar"))], vec![]);
+        assert!(endpoint_json_schema(&endpoint, &TypeRegistry::new()).is_err());
+    }
+
+    #[test]
+    fn test_conflicting_definitions() {
+        let mut registry = TypeRegistry::new();
+        let fields = vec![Field::new("symbol", Type::String)];
+        registry.register(&Type::struct_("Symbol", fields.clone())).unwrap();
+        registry.register(&Type::datatable("Symbol", fields)).unwrap();
+        let other = Type::struct_("Symbol", vec![Field::new("name", Type::String)]);
+        assert!(registry.register(&other).is_err());
+    }
+}
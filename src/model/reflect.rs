@@ -0,0 +1,197 @@
+use std::net::IpAddr;
+
+use crate::libs::datatable::RDataTable;
+use crate::libs::pagination::{Page, Pagination};
+use crate::libs::types::{Address, BlockchainAddress, BlockchainTransactionHash, H256};
+use crate::model::{Field, Type};
+
+/// `EndpointType` maps a Rust type to the `Type` used in an endpoint schema.
+///
+/// Implemented for primitives here and derived for structs and enums with
+/// `#[derive(EndpointStruct)]` and `#[derive(EndpointEnum)]`.
+pub trait EndpointType {
+    /// Returns the `Type` of `Self` (e.g. `Type::BigInt` for `i64`)
+    fn endpoint_type() -> Type;
+}
+
+/// `EndpointFields` lists the fields of a struct as they appear in an endpoint schema.
+pub trait EndpointFields {
+    /// The name of the struct in an endpoint schema
+    const NAME: &'static str;
+
+    /// Returns the fields of `Self` in declaration order
+    fn endpoint_fields() -> Vec<Field>;
+}
+
+macro_rules! impl_endpoint_type {
+    ($ty: expr => $($t: ty),+) => {
+        $(
+            impl EndpointType for $t {
+                fn endpoint_type() -> Type {
+                    $ty
+                }
+            }
+        )+
+    };
+}
+
+impl_endpoint_type!(Type::Int => i8, i16, i32, u8, u16);
+impl_endpoint_type!(Type::BigInt => i64, u32, u64);
+impl_endpoint_type!(Type::Numeric => f32, f64);
+impl_endpoint_type!(Type::Boolean => bool);
+impl_endpoint_type!(Type::String => String);
+impl_endpoint_type!(Type::Inet => IpAddr);
+impl_endpoint_type!(Type::Object => serde_json::Value);
+impl_endpoint_type!(Type::Unit => ());
+impl_endpoint_type!(Type::BlockchainDecimal => rust_decimal::Decimal);
+impl_endpoint_type!(Type::BlockchainAddress => BlockchainAddress, Address);
+impl_endpoint_type!(Type::BlockchainTransactionHash => BlockchainTransactionHash, H256);
+
+impl<T: EndpointType> EndpointType for Option<T> {
+    fn endpoint_type() -> Type {
+        Type::optional(T::endpoint_type())
+    }
+}
+
+impl<T: EndpointType> EndpointType for Vec<T> {
+    fn endpoint_type() -> Type {
+        Type::vec(T::endpoint_type())
+    }
+}
+
+impl<T: EndpointType> EndpointType for Box<T> {
+    fn endpoint_type() -> Type {
+        T::endpoint_type()
+    }
+}
+
+/// A `RDataTable<T>` is mapped to `Type::DataTable` with the name and fields of `T`.
+impl<T: EndpointFields> EndpointType for RDataTable<T> {
+    fn endpoint_type() -> Type {
+        Type::datatable(T::NAME, T::endpoint_fields())
+    }
+}
+
+impl EndpointType for Pagination {
+    fn endpoint_type() -> Type {
+        Type::pagination()
+    }
+}
+
+/// A `Page<T>` is mapped to `Type::page` with the name and fields of `T`.
+impl<T: EndpointFields> EndpointType for Page<T> {
+    fn endpoint_type() -> Type {
+        Type::page(T::NAME, T::endpoint_fields())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::libs::ws::{WsRequest, WsStreamRequest};
+    use crate::model::{Endpoint, EndpointEnum, EndpointStruct, EnumVariant};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, EndpointEnum)]
+    pub enum Side {
+        /// Buy side
+        Buy = 1,
+        Sell,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, EndpointStruct)]
+    pub struct Symbol {
+        pub symbol: String,
+        pub side: Side,
+    }
+
+    /// Lists the symbols of the user
+    #[derive(Serialize, Deserialize, Clone, Debug, Endpoint)]
+    #[endpoint(code = 10020, name = "UserListSymbols", stream = Symbol)]
+    pub struct UserListSymbolsRequest {
+        pub limit: Option<i32>,
+        #[endpoint(ty = Type::TimeStampMs)]
+        pub since: i64,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, EndpointStruct)]
+    pub struct UserListSymbolsResponse {
+        pub data: RDataTable<Symbol>,
+    }
+
+    #[test]
+    fn test_derive_endpoint_schema() {
+        let schema = UserListSymbolsRequest::schema();
+        assert_eq!(schema.name, "UserListSymbols");
+        assert_eq!(schema.code, 10020);
+        assert_eq!(schema.description, "Lists the symbols of the user");
+        assert_eq!(
+            schema.parameters,
+            vec![
+                Field::new("limit", Type::optional(Type::Int)),
+                Field::new("since", Type::TimeStampMs),
+            ]
+        );
+        let side = Type::enum_(
+            "Side",
+            vec![
+                EnumVariant::new_with_comment("Buy", 1, "Buy side").with_serialized_name(),
+                EnumVariant::new("Sell", 2).with_serialized_name(),
+            ],
+        );
+        assert_eq!(
+            schema.returns,
+            vec![Field::new(
+                "data",
+                Type::datatable("Symbol", vec![Field::new("symbol", Type::String), Field::new("side", side)]),
+            )]
+        );
+        assert_eq!(schema.stream_response, Some(Symbol::endpoint_type()));
+        fn stream_of<T: WsStreamRequest>() -> Type
+        where
+            T::Stream: EndpointType,
+        {
+            T::Stream::endpoint_type()
+        }
+        assert_eq!(stream_of::<UserListSymbolsRequest>(), Symbol::endpoint_type());
+        assert_eq!(schema.code, UserListSymbolsRequest::METHOD_ID);
+        assert_eq!(UserListSymbolsRequest::SCHEMA, "");
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, EndpointEnum)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    pub enum OrderType {
+        StopLimit = 1,
+        #[serde(rename = "mkt")]
+        Market,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, Default, EndpointStruct)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Order {
+        pub order_id: i64,
+        #[serde(rename = "px")]
+        pub price: f64,
+        #[serde(skip)]
+        #[allow(dead_code)]
+        pub local_only: bool,
+    }
+
+    #[test]
+    fn test_derive_serde_names() {
+        let names: Vec<String> = Order::endpoint_fields().into_iter().map(|x| x.name).collect();
+        let json = serde_json::to_value(Order::default()).unwrap();
+        let keys: Vec<String> = json.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(names, vec!["orderId", "px"]);
+        assert_eq!(names.len(), keys.len());
+        assert!(names.iter().all(|x| keys.contains(x)));
+        let Type::Enum { variants, .. } = OrderType::endpoint_type() else {
+            panic!("expected an enum");
+        };
+        assert_eq!(variants[0].name, "STOP_LIMIT");
+        assert_eq!(serde_json::to_value(OrderType::StopLimit).unwrap(), "STOP_LIMIT");
+        assert_eq!(variants[1].name, "mkt");
+    }
+}
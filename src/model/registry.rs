@@ -0,0 +1,91 @@
+use eyre::{bail, Result};
+use std::collections::BTreeMap;
+
+use crate::model::{EndpointSchema, Service, Type};
+
+/// `TypeRegistry` indexes the named types (structs, datatables and enums) declared inline in
+/// endpoint schemas, so that `StructRef`, `EnumRef` and `DataTableIdentifer` can be resolved.
+#[derive(Clone, Debug, Default)]
+pub struct TypeRegistry {
+    types: BTreeMap<String, Type>,
+}
+
+impl TypeRegistry {
+    /// Creates an empty `TypeRegistry`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a `TypeRegistry` with the named types of all endpoints of the service.
+    pub fn from_service(service: &Service) -> Result<Self> {
+        let mut this = Self::new();
+        for endpoint in &service.endpoints {
+            this.register_endpoint(endpoint)?;
+        }
+        Ok(this)
+    }
+
+    /// Registers the named types used by the parameters, returns and stream response of the endpoint.
+    pub fn register_endpoint(&mut self, endpoint: &EndpointSchema) -> Result<()> {
+        for field in endpoint.parameters.iter().chain(&endpoint.returns) {
+            self.register(&field.ty)?;
+        }
+        if let Some(ty) = &endpoint.stream_response {
+            self.register(ty)?;
+        }
+        Ok(())
+    }
+
+    /// Registers `ty` and every named type nested in it. Fails if a name is already registered with
+    /// a different definition. A struct and a datatable with the same fields are the same row type.
+    pub fn register(&mut self, ty: &Type) -> Result<()> {
+        let name = match ty {
+            Type::Struct { name, fields } | Type::DataTable { name, fields } => {
+                for field in fields {
+                    self.register(&field.ty)?;
+                }
+                name
+            }
+            Type::Enum { name, .. } => name,
+            Type::Vec(ty) | Type::Optional(ty) => return self.register(ty),
+            _ => return Ok(()),
+        };
+        match self.types.get(name) {
+            Some(existing) if !same_definition(existing, ty) => bail!("Conflicting definitions of type {}", name),
+            Some(_) => {}
+            None => {
+                self.types.insert(name.clone(), ty.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the definition of the named type.
+    pub fn get(&self, name: &str) -> Option<&Type> {
+        self.types.get(name)
+    }
+
+    /// Resolves references to their definitions, other types are returned as is.
+    /// Returns `None` if the referenced type is not registered.
+    pub fn resolve<'a>(&'a self, ty: &'a Type) -> Option<&'a Type> {
+        match ty {
+            Type::StructRef(name) | Type::EnumRef(name) | Type::DataTableIdentifer { name } => self.get(name),
+            _ => Some(ty),
+        }
+    }
+
+    /// Iterates over the registered types by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Type)> {
+        self.types.iter()
+    }
+}
+
+fn same_definition(a: &Type, b: &Type) -> bool {
+    match (a, b) {
+        (
+            Type::Struct { fields: a, .. } | Type::DataTable { fields: a, .. },
+            Type::Struct { fields: b, .. } | Type::DataTable { fields: b, .. },
+        ) => a == b,
+        (a, b) => a == b,
+    }
+}
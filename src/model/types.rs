@@ -1,3 +1,4 @@
+use convert_case::{Case, Casing};
 use serde::*;
 
 /// `Field` is a struct that represents the parameters and returns in an endpoint schema.
@@ -15,6 +16,16 @@ impl Field {
     pub fn new(name: impl Into<String>, ty: Type) -> Self {
         Self { name: name.into(), ty }
     }
+
+    /// Returns the JSON key of the field: snake_case names are serialized in camelCase, other names
+    /// (e.g. the serialized names of derived schemas) as-is.
+    pub fn serialized_name(&self) -> String {
+        if self.name == self.name.to_case(Case::Snake) {
+            self.name.to_case(Case::Camel)
+        } else {
+            self.name.clone()
+        }
+    }
 }
 
 /// `EnumVariant` is a struct that represents the variants of an enum.
@@ -28,6 +39,11 @@ pub struct EnumVariant {
 
     /// A comment added by `new_with_comment` method
     pub comment: String,
+
+    /// Whether `name` is serialized as-is (e.g. by `#[derive(EndpointEnum)]`) rather than in
+    /// PascalCase, set by `with_serialized_name` method
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub serialized: bool,
 }
 
 impl EnumVariant {
@@ -38,6 +54,7 @@ impl EnumVariant {
             name: name.into(),
             value,
             comment: "".to_owned(),
+            serialized: false,
         }
     }
 
@@ -47,6 +64,22 @@ impl EnumVariant {
             name: name.into(),
             value,
             comment: comment.into(),
+            serialized: false,
+        }
+    }
+
+    /// Marks `name` as the serialized name of the variant.
+    pub fn with_serialized_name(mut self) -> Self {
+        self.serialized = true;
+        self
+    }
+
+    /// Returns the name of the variant in JSON, see `serialized`.
+    pub fn serialized_name(&self) -> String {
+        if self.serialized {
+            self.name.clone()
+        } else {
+            self.name.to_case(Case::Pascal)
         }
     }
 }
@@ -128,6 +161,33 @@ impl Type {
             variants: fields,
         }
     }
+
+    /// Creates the `Type::Struct` of a `Pagination` parameter, see [`crate::libs::pagination::Pagination`].
+    pub fn pagination() -> Self {
+        Self::struct_(
+            "Pagination",
+            vec![
+                Field::new("limit", Self::optional(Self::Int)),
+                Field::new("offset", Self::optional(Self::Int)),
+                Field::new("cursor", Self::optional(Self::String)),
+            ],
+        )
+    }
+
+    /// Creates the `Type::Struct` named `{name}Page` of a page of rows with the given fields,
+    /// see [`crate::libs::pagination::Page`].
+    pub fn page(name: impl Into<String>, fields: Vec<Field>) -> Self {
+        let name = name.into();
+        Self::struct_(
+            format!("{}Page", name),
+            vec![
+                Field::new("rows", Self::vec(Self::struct_(name, fields))),
+                Field::new("total", Self::optional(Self::BigInt)),
+                Field::new("next_cursor", Self::optional(Self::String)),
+            ],
+        )
+    }
+
     pub fn try_unwrap(self) -> Option<Self> {
         match self {
             Self::Vec(v) => Some(*v),